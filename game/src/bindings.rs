@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use miniquad::KeyCode;
+use snake::devices::DeviceConfig;
+
+use crate::components::Action;
+
+/// Where key bindings are read from at start up and written to when they are
+/// changed in the controls menu.
+pub const BINDINGS_PATH: &str = "snek.keys";
+
+/// Keys that can be bound, written to the config file by their `Debug` name.
+const KEYS: &[KeyCode] = &[
+    KeyCode::Space,
+    KeyCode::Apostrophe,
+    KeyCode::Comma,
+    KeyCode::Minus,
+    KeyCode::Period,
+    KeyCode::Slash,
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::Semicolon,
+    KeyCode::Equal,
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::LeftBracket,
+    KeyCode::Backslash,
+    KeyCode::RightBracket,
+    KeyCode::GraveAccent,
+    KeyCode::Escape,
+    KeyCode::Enter,
+    KeyCode::Tab,
+    KeyCode::Backspace,
+    KeyCode::Insert,
+    KeyCode::Delete,
+    KeyCode::Right,
+    KeyCode::Left,
+    KeyCode::Down,
+    KeyCode::Up,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::Kp0,
+    KeyCode::Kp1,
+    KeyCode::Kp2,
+    KeyCode::Kp3,
+    KeyCode::Kp4,
+    KeyCode::Kp5,
+    KeyCode::Kp6,
+    KeyCode::Kp7,
+    KeyCode::Kp8,
+    KeyCode::Kp9,
+    KeyCode::KpEnter,
+    KeyCode::LeftShift,
+    KeyCode::LeftControl,
+    KeyCode::LeftAlt,
+    KeyCode::RightShift,
+    KeyCode::RightControl,
+    KeyCode::RightAlt,
+];
+
+pub fn key_name(key: KeyCode) -> String {
+    format!("{:?}", key)
+}
+
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEYS.iter().copied().find(|key| key_name(*key) == name)
+}
+
+#[derive(Debug)]
+pub enum BindingsError {
+    Io(io::Error),
+    Malformed { line: usize },
+    UnknownAction { line: usize },
+    UnknownKey { line: usize },
+}
+
+impl From<io::Error> for BindingsError {
+    fn from(err: io::Error) -> Self {
+        BindingsError::Io(err)
+    }
+}
+
+impl fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingsError::Io(err) => write!(f, "{}", err),
+            BindingsError::Malformed { line } => write!(f, "malformed binding on line {}", line),
+            BindingsError::UnknownAction { line } => write!(f, "unknown action on line {}", line),
+            BindingsError::UnknownKey { line } => write!(f, "unknown key on line {}", line),
+        }
+    }
+}
+
+/// Which keys trigger which actions. An action can have any number of keys
+/// and a key can trigger more than one action.
+///
+/// The config file has one line per action, its name followed by its keys,
+/// and a line for each of the touch and gamepad thresholds:
+///
+/// ```text
+/// move_up Up W
+/// pause Escape P
+/// dead_zone 0.3
+/// swipe_threshold 0.08
+/// tap_centre 0.15
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bindings {
+    keys: HashMap<Action, Vec<KeyCode>>,
+    pub devices: DeviceConfig,
+    /// Set while the controls menu waits for the player to press a key.
+    capturing: bool,
+    captured: Option<KeyCode>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let keys = Action::ALL
+            .iter()
+            .map(|action| {
+                let keys = match action {
+                    Action::MoveLeft => vec![KeyCode::Left, KeyCode::A],
+                    Action::MoveRight => vec![KeyCode::Right, KeyCode::D],
+                    Action::MoveUp => vec![KeyCode::Up, KeyCode::W],
+                    Action::MoveDown => vec![KeyCode::Down, KeyCode::S],
+                    Action::Player2Left => vec![KeyCode::J],
+                    Action::Player2Right => vec![KeyCode::L],
+                    Action::Player2Up => vec![KeyCode::I],
+                    Action::Player2Down => vec![KeyCode::K],
+                    Action::Pause => vec![KeyCode::Escape, KeyCode::P],
+                    Action::Confirm => vec![KeyCode::Enter, KeyCode::Space],
+                    Action::Back => vec![KeyCode::Escape, KeyCode::Backspace],
+                };
+                (*action, keys)
+            })
+            .collect();
+        Self {
+            keys,
+            devices: DeviceConfig::default(),
+            capturing: false,
+            captured: None,
+        }
+    }
+}
+
+impl Bindings {
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.keys.get(&action).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Every action `key` is bound to.
+    pub fn actions(&self, key: KeyCode) -> impl Iterator<Item = Action> + '_ {
+        Action::ALL
+            .iter()
+            .copied()
+            .filter(move |action| self.keys(*action).contains(&key))
+    }
+
+    /// Binds `key` to `action`, or unbinds it if it already was. The last key
+    /// of an action can't be unbound, so nothing is left unreachable.
+    pub fn toggle(&mut self, action: Action, key: KeyCode) {
+        let keys = self.keys.entry(action).or_insert_with(Vec::new);
+        match keys.iter().position(|k| *k == key) {
+            Some(index) if keys.len() > 1 => {
+                keys.remove(index);
+            }
+            Some(_) => {}
+            None => keys.push(key),
+        }
+    }
+
+    /// Makes the next key pressed go to `take_captured` instead of
+    /// triggering actions.
+    pub fn start_capture(&mut self) {
+        self.capturing = true;
+        self.captured = None;
+    }
+
+    /// Holds on to `key` if a capture is running. Returns whether it did.
+    pub fn capture(&mut self, key: KeyCode) -> bool {
+        if !self.capturing {
+            return false;
+        }
+        self.capturing = false;
+        self.captured = Some(key);
+        true
+    }
+
+    pub fn take_captured(&mut self) -> Option<KeyCode> {
+        self.captured.take()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), BindingsError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, BindingsError> {
+        let contents = fs::read_to_string(path)?;
+        Self::parse(&contents)
+    }
+
+    /// Actions missing from `contents` keep their default keys.
+    pub fn parse(contents: &str) -> Result<Self, BindingsError> {
+        let mut bindings = Bindings::default();
+        for (index, line) in contents.lines().enumerate() {
+            let line_number = index + 1;
+            let mut parts = line.split_whitespace();
+            let name = match parts.next() {
+                Some(name) if name.starts_with('#') => continue,
+                Some(name) => name,
+                None => continue,
+            };
+            let threshold = match name {
+                "dead_zone" => Some(&mut bindings.devices.dead_zone),
+                "swipe_threshold" => Some(&mut bindings.devices.swipe_threshold),
+                "tap_centre" => Some(&mut bindings.devices.tap_centre),
+                _ => None,
+            };
+            if let Some(threshold) = threshold {
+                *threshold = match (parts.next().map(str::parse::<f32>), parts.next()) {
+                    (Some(Ok(value)), None) => value,
+                    _ => return Err(BindingsError::Malformed { line: line_number }),
+                };
+                continue;
+            }
+            let action = Action::from_name(name)
+                .ok_or(BindingsError::UnknownAction { line: line_number })?;
+            let keys = parts
+                .map(|key| {
+                    key_from_name(key).ok_or(BindingsError::UnknownKey { line: line_number })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if keys.is_empty() {
+                return Err(BindingsError::Malformed { line: line_number });
+            }
+            bindings.keys.insert(action, keys);
+        }
+        Ok(bindings)
+    }
+}
+
+impl fmt::Display for Bindings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for action in Action::ALL.iter() {
+            write!(f, "{}", action.name())?;
+            for key in self.keys(*action) {
+                write!(f, " {}", key_name(*key))?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "dead_zone {}", self.devices.dead_zone)?;
+        writeln!(f, "swipe_threshold {}", self.devices.swipe_threshold)?;
+        writeln!(f, "tap_centre {}", self.devices.tap_centre)?;
+        Ok(())
+    }
+}
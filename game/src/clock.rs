@@ -0,0 +1,13 @@
+use miniquad::date;
+
+pub use snake::clock::*;
+
+/// Wall clock time, as reported by miniquad.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SystemTime;
+
+impl TimeSource for SystemTime {
+    fn now(&self) -> f64 {
+        date::now()
+    }
+}
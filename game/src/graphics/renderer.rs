@@ -9,24 +9,11 @@ use crate::graphics::font;
 use crate::shaders;
 use crate::utils;
 
+pub use snake::components::AssetIdentity;
+
 pub type Materials = HashMap<AssetIdentity, MaterialAsset>;
 pub type Meshes = HashMap<AssetIdentity, MeshAsset>;
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
-pub struct AssetIdentity(pub String);
-
-impl From<String> for AssetIdentity {
-    fn from(v: String) -> Self {
-        Self(v)
-    }
-}
-
-impl From<&'_ str> for AssetIdentity {
-    fn from(v: &'_ str) -> Self {
-        Self(v.to_owned())
-    }
-}
-
 #[derive(Debug, Clone)]
 pub struct SpriteRenderCommand {
     pub binding: AssetIdentity,
@@ -1,48 +1,66 @@
 use miniquad::*;
 use megaui::hash;
-use stages::GameState;
+use bindings::{Bindings, BindingsError, BINDINGS_PATH};
+use snake::devices::{self, TouchTracker};
+use snake::net::NetConfig;
+use stages::{GameOptions, Title};
 
+mod bindings;
+mod clock;
 mod components;
-mod events;
 mod graphics;
+mod settings;
 mod shaders;
 mod stages;
 mod systems;
 mod ui;
 mod utils;
 
+/// Touch id the mouse is tracked under. Real touches count up from 0, so
+/// this one is out of their way.
+const MOUSE_TOUCH_ID: u64 = u64::MAX;
+
 struct SnakeGame {
     stages: stages::StageStack,
     renderer: graphics::MainRenderer,
     input: components::Input,
+    settings: settings::SharedSettings,
+    touches: TouchTracker,
+    mouse_down: bool,
     ui: ui::UiContext,
+    last_frame: f64,
+    delta_time: f64,
 }
 
 impl SnakeGame {
-    pub fn new(ctx: Context) -> Self {
+    pub fn new(ctx: Context, options: GameOptions) -> Self {
         let (width, height) = ctx.screen_size();
         let mut renderer = graphics::MainRenderer::new(ctx);
 
         let mut input = components::Input::default();
         input.width = width;
         input.height = height;
-        let mut stages = stages::new_stage_stack(16);
-        let init_state = GameState::new(&input, &mut renderer);
-        let game_stage = Box::new(init_state);
-
-        stages.push(game_stage as Box<dyn stages::Stage>);
+        let settings = options.settings.clone();
+        let mut stages = stages::StageStack::with_capacity(16);
+        stages.push(Box::new(Title::new(&input, options)), &mut renderer);
         let ui = ui::UiContext::new();
 
         SnakeGame {
             stages,
             renderer,
             input,
+            settings,
+            touches: TouchTracker::default(),
+            mouse_down: false,
             ui,
+            last_frame: date::now(),
+            delta_time: 0.,
         }
     }
 
+    /// Real time elapsed between the last two frames, in seconds.
     pub fn delta_time(&self) -> f32 {
-        0.
+        self.delta_time as f32
     }
 }
 
@@ -54,27 +72,14 @@ impl EventHandlerFree for SnakeGame {
     }
 
     fn update(&mut self) {
-        let stage = match self.stages.last_mut() {
-            Some(s) => s,
-            _ => return,
-        };
-        let next_stage = stage.update(&self.input, &mut self.renderer);
-        match next_stage {
-            stages::NextStage::Push(mut new_stage) => {
-                stage.exit(&mut self.renderer);
-                new_stage.enter(&mut self.renderer);
-                self.stages.push(new_stage);
-            }
-            stages::NextStage::Pop => {
-                stage.exit(&mut self.renderer);
-                self.stages.pop().expect("Popped an Empty StageStack");
-                match self.stages.last_mut() {
-                    Some(s) => s.enter(&mut self.renderer),
-                    _ => {}
-                };
-            }
-            _ => {}
-        };
+        let now = date::now();
+        self.delta_time = now - self.last_frame;
+        self.last_frame = now;
+
+        self.stages.update(&self.input, &mut self.renderer);
+        if self.stages.is_empty() {
+            self.renderer.ctx.request_quit();
+        }
         self.ui.process_input(&self.input);
         self.renderer.load_assets();
 
@@ -88,9 +93,7 @@ impl EventHandlerFree for SnakeGame {
     }
 
     fn draw(&mut self) {
-        for stage in self.stages.iter_mut() {
-            stage.draw(&mut self.renderer);
-        }
+        self.stages.draw(&mut self.renderer);
         self.ui.draw(self.delta_time());
 
         self.renderer.draw();
@@ -101,30 +104,160 @@ impl EventHandlerFree for SnakeGame {
             return;
         }
 
-        match keycode {
-            KeyCode::Left | KeyCode::A => {
-                self.input.go_left = true;
+        let mut settings = self.settings.borrow_mut();
+        if settings.bindings.capture(keycode) {
+            return;
+        }
+        for action in settings.bindings.actions(keycode) {
+            self.input.press(action);
+        }
+    }
+
+    fn touch_event(&mut self, phase: TouchPhase, id: u64, x: f32, y: f32) {
+        let phase = match phase {
+            TouchPhase::Started => devices::TouchPhase::Started,
+            TouchPhase::Moved => devices::TouchPhase::Moved,
+            TouchPhase::Ended => devices::TouchPhase::Ended,
+            TouchPhase::Cancelled => devices::TouchPhase::Cancelled,
+        };
+        self.touches.config = self.settings.borrow().bindings.devices;
+        self.touches.touch(phase, id, x, y, &mut self.input);
+    }
+
+    /// The mouse swipes and taps like a finger, so touch controls can be
+    /// tried out on desktop.
+    fn mouse_button_down_event(&mut self, button: MouseButton, x: f32, y: f32) {
+        if button == MouseButton::Left {
+            self.mouse_down = true;
+            self.touch_event(TouchPhase::Started, MOUSE_TOUCH_ID, x, y);
+        }
+    }
+
+    fn mouse_motion_event(&mut self, x: f32, y: f32) {
+        if self.mouse_down {
+            self.touch_event(TouchPhase::Moved, MOUSE_TOUCH_ID, x, y);
+        }
+    }
+
+    fn mouse_button_up_event(&mut self, button: MouseButton, x: f32, y: f32) {
+        if button == MouseButton::Left && self.mouse_down {
+            self.mouse_down = false;
+            self.touch_event(TouchPhase::Ended, MOUSE_TOUCH_ID, x, y);
+        }
+    }
+}
+
+/// `--record <path>` saves a replay of every run, `--replay <path>` plays
+/// one back, `--seed <n>` fixes the food RNG, `--wrap` takes the walls
+/// away so the snake wraps around the arena, `--players <n>` sets how
+/// many snakes share the keyboard when "Play" is picked and `--bot <name>`
+/// hands the first of them to a bot, for a demo. `--mode <name>` is the
+/// mode picked to begin with, one of `classic`, `time_attack`, `survival`
+/// and `zen`. `--level <path>` plays on that level instead of picking one
+/// from the `levels` folder.
+///
+/// `--host <local> <peer>` and `--join <local> <peer>` play a two player
+/// game over UDP, from and to the given `address:port`s. `--net-delay
+/// <ticks>` sets the input delay, and `--net-loss <share>`, `--net-latency
+/// <seconds>` and `--net-jitter <seconds>` make the connection worse on
+/// purpose, to try it out on one machine. A game over the network always
+/// has two players.
+fn parse_options() -> GameOptions {
+    let mut options = GameOptions::default();
+    options.settings.borrow_mut().bindings = load_bindings();
+    let mut net: Option<NetConfig> = None;
+    let (mut delay, mut loss, mut latency, mut jitter) = (None, None, None, None);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--host" | "--join" => {
+                let player = if arg == "--host" { 0 } else { 1 };
+                let local = args.next().and_then(|addr| addr.parse().ok());
+                let peer = args.next().and_then(|addr| addr.parse().ok());
+                match (local, peer) {
+                    (Some(local), Some(peer)) => net = Some(NetConfig::new(local, peer, player)),
+                    _ => eprintln!("{} needs a local and a peer address", arg),
+                }
             }
-            KeyCode::Right | KeyCode::D => {
-                self.input.go_right = true;
+            "--net-delay" => delay = args.next().and_then(|delay| delay.parse().ok()),
+            "--net-loss" => loss = args.next().and_then(|loss| loss.parse().ok()),
+            "--net-latency" => latency = args.next().and_then(|latency| latency.parse().ok()),
+            "--net-jitter" => jitter = args.next().and_then(|jitter| jitter.parse().ok()),
+            "--record" => options.record_path = args.next().map(Into::into),
+            "--seed" => options.seed = args.next().and_then(|seed| seed.parse().ok()),
+            "--wrap" => options.arena.walls = snake::arena::WallMode::Wrap,
+            "--level" => {
+                let path = match args.next() {
+                    Some(path) => path,
+                    _ => continue,
+                };
+                match snake::level::Level::load(&path) {
+                    Ok(level) => options.level = Some(level),
+                    Err(err) => eprintln!("Failed to load level {}: {}", path, err),
+                }
             }
-            KeyCode::Up | KeyCode::W => {
-                self.input.go_up = true;
+            "--mode" => {
+                let name = args.next().unwrap_or_default();
+                match snake::mode::GameMode::from_name(&name) {
+                    Some(mode) => options.mode = mode,
+                    None => eprintln!("Unknown mode {:?}", name),
+                }
             }
-            KeyCode::Down | KeyCode::S => {
-                self.input.go_down = true;
+            "--bot" => {
+                let name = args.next().unwrap_or_default();
+                match snake::ai::Bot::from_name(&name) {
+                    Some(bot) => options.bots = vec![Some(bot)],
+                    None => eprintln!("Unknown bot {:?}", name),
+                }
             }
-            KeyCode::Escape => {
-                self.input.pause = true;
-                self.input.go_back = true;
+            "--players" => {
+                let most = components::Action::MAX_PLAYERS;
+                if let Some(players) = args.next().and_then(|players| players.parse::<u8>().ok()) {
+                    options.players = players.clamp(1, most);
+                }
+            }
+            "--replay" => {
+                let path = match args.next() {
+                    Some(path) => path,
+                    _ => continue,
+                };
+                match snake::replay::Replay::load(&path) {
+                    Ok(replay) => options.replay = Some(replay),
+                    Err(err) => eprintln!("Failed to load replay {}: {}", path, err),
+                }
             }
             _ => {}
         }
     }
+    if let Some(net) = &mut net {
+        net.delay = delay.unwrap_or(net.delay);
+        net.loss = loss.unwrap_or(net.loss);
+        net.latency = latency.unwrap_or(net.latency);
+        net.jitter = jitter.unwrap_or(net.jitter);
+        // One snake on each end, whatever `--players` said. Levels are
+        // checked against this when the mode is picked.
+        options.players = 2;
+    }
+    options.net = net;
+    options
+}
+
+/// Falls back to the default keys when there's no config file yet, or the
+/// one there can't be read.
+fn load_bindings() -> Bindings {
+    match Bindings::load(BINDINGS_PATH) {
+        Ok(bindings) => bindings,
+        Err(BindingsError::Io(_)) => Bindings::default(),
+        Err(err) => {
+            eprintln!("Failed to load key bindings from {}: {}", BINDINGS_PATH, err);
+            Bindings::default()
+        }
+    }
 }
 
 fn main() {
+    let options = parse_options();
     miniquad::start(conf::Conf::default(), |ctx| {
-        UserData::free(SnakeGame::new(ctx))
+        UserData::free(SnakeGame::new(ctx, options))
     });
 }
@@ -0,0 +1,25 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::bindings::Bindings;
+
+/// Player preferences, changed from the settings menu.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Draws the arrow showing which way the snake will turn next.
+    pub show_direction: bool,
+    pub bindings: Bindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            show_direction: true,
+            bindings: Bindings::default(),
+        }
+    }
+}
+
+/// Settings are read by the game and written by menus on other stages, so
+/// every stage holds a handle to the same copy.
+pub type SharedSettings = Rc<RefCell<Settings>>;
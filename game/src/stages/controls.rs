@@ -0,0 +1,150 @@
+use glam::Vec2;
+
+use crate::{
+    bindings::{self, Bindings, BINDINGS_PATH},
+    components::{Action, Input},
+    graphics,
+    settings::SharedSettings,
+    stages::{
+        menu::{self, Menu},
+        NextStage, Stage,
+    },
+};
+
+const TITLE: &str = "Controls";
+const PROMPT: &str = "Press a key to add or remove it, Escape to cancel";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ControlsEntry {
+    Bind(Action),
+    Reset,
+    Back,
+}
+
+/// Lists the keys bound to every action and lets the player change them.
+/// Changes are written to `BINDINGS_PATH` as soon as they are made.
+pub struct Controls {
+    menu: Menu<ControlsEntry>,
+    settings: SharedSettings,
+    /// The action waiting for a key, if any.
+    listening: Option<Action>,
+}
+
+fn binding_label(bindings: &Bindings, action: Action) -> String {
+    let keys: Vec<String> = bindings
+        .keys(action)
+        .iter()
+        .map(|key| bindings::key_name(*key))
+        .collect();
+    format!("{}: {}", action.label(), keys.join(" "))
+}
+
+impl Controls {
+    pub fn new(settings: SharedSettings) -> Self {
+        let mut entries: Vec<(ControlsEntry, String)> = {
+            let bindings = &settings.borrow().bindings;
+            Action::ALL
+                .iter()
+                .map(|action| {
+                    (
+                        ControlsEntry::Bind(*action),
+                        binding_label(bindings, *action),
+                    )
+                })
+                .collect()
+        };
+        entries.push((ControlsEntry::Reset, "Reset Defaults".to_string()));
+        entries.push((ControlsEntry::Back, "Back".to_string()));
+        Self {
+            menu: Menu::new(entries),
+            settings,
+            listening: None,
+        }
+    }
+
+    fn relabel(&mut self, renderer: &mut graphics::MainRenderer) {
+        let labels: Vec<(Action, String)> = {
+            let bindings = &self.settings.borrow().bindings;
+            Action::ALL
+                .iter()
+                .map(|action| (*action, binding_label(bindings, *action)))
+                .collect()
+        };
+        for (action, label) in labels {
+            self.menu
+                .set_label(ControlsEntry::Bind(action), label, renderer);
+        }
+    }
+
+    fn save(&self) {
+        if let Err(err) = self.settings.borrow().bindings.save(BINDINGS_PATH) {
+            eprintln!("Failed to save key bindings to {}: {}", BINDINGS_PATH, err);
+        }
+    }
+
+    /// Applies the key the player pressed while this stage was listening.
+    fn listen(&mut self, action: Action, renderer: &mut graphics::MainRenderer) {
+        let key = match self.settings.borrow_mut().bindings.take_captured() {
+            Some(key) => key,
+            None => return,
+        };
+        self.listening = None;
+        if key == miniquad::KeyCode::Escape {
+            return;
+        }
+        self.settings.borrow_mut().bindings.toggle(action, key);
+        self.save();
+        self.relabel(renderer);
+    }
+}
+
+impl Stage for Controls {
+    fn name(&self) -> &'static str {
+        "Controls"
+    }
+
+    fn is_transparent(&self) -> bool {
+        true
+    }
+
+    fn enter(&mut self, renderer: &mut graphics::MainRenderer) {
+        menu::load_assets(renderer);
+        menu::load_text(renderer, TITLE);
+        menu::load_text(renderer, PROMPT);
+        self.menu.load(renderer);
+    }
+
+    fn update(&mut self, input: &Input, renderer: &mut graphics::MainRenderer) -> NextStage {
+        if let Some(action) = self.listening {
+            self.listen(action, renderer);
+            return NextStage::Noop;
+        }
+        if input.pressed(Action::Back) {
+            return NextStage::Pop;
+        }
+        match self.menu.update(input) {
+            Some(ControlsEntry::Bind(action)) => {
+                self.settings.borrow_mut().bindings.start_capture();
+                self.listening = Some(action);
+                NextStage::Noop
+            }
+            Some(ControlsEntry::Reset) => {
+                self.settings.borrow_mut().bindings = Bindings::default();
+                self.save();
+                self.relabel(renderer);
+                NextStage::Noop
+            }
+            Some(ControlsEntry::Back) => NextStage::Pop,
+            None => NextStage::Noop,
+        }
+    }
+
+    fn draw(&mut self, renderer: &mut graphics::MainRenderer) {
+        menu::draw_overlay(renderer);
+        menu::draw_text(renderer, TITLE, Vec2::new(-4., 9.));
+        if self.listening.is_some() {
+            menu::draw_text(renderer, PROMPT, Vec2::new(-4., 7.));
+        }
+        self.menu.draw(renderer, Vec2::new(-4., 5.));
+    }
+}
@@ -1,136 +1,547 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::rc::Rc;
+
 use components::Input;
 use graphics::renderer;
+use miniquad::date;
+use snake::ai::{Bot, SnakeController};
+use snake::arena::Arena;
+use snake::food::{FoodKind, FOOD_TABLE};
+use snake::level::Level;
+use snake::mode::GameMode;
+use snake::net::{self, Lockstep, NetConfig};
+use snake::replay::{Playback, Replay};
+use snake::snapshot::Snapshot;
 
+use crate::clock::{self, SimClock, SystemTime};
 use crate::components;
 use crate::graphics::{self};
-use crate::stages::{NextStage, Paused, Stage};
-use crate::systems::{self, GameWorld};
+use crate::settings::SharedSettings;
+use crate::stages::{GameOver, NextStage, Paused, Stage};
+use crate::systems;
+use snake::systems::{GameWorld, RunStats};
+
+#[derive(Debug, Clone)]
+pub struct GameOptions {
+    /// Where to write a replay of each run once it ends.
+    pub record_path: Option<PathBuf>,
+    /// Feeds this replay to the simulation instead of the player's input.
+    pub replay: Option<Replay>,
+    /// Seeds the first run. Later runs are seeded from the run before.
+    pub seed: Option<u64>,
+    pub arena: Arena,
+    /// Played on in place of `arena` when set.
+    pub level: Option<Level>,
+    pub mode: GameMode,
+    /// Snakes sharing the keyboard, or two for a game over the network.
+    pub players: u8,
+    /// The bot steering each player's snake, indexed by player. Players
+    /// without one are steered from the keyboard.
+    pub bots: Vec<Option<Bot>>,
+    /// Plays a two player game against a peer over the network.
+    pub net: Option<NetConfig>,
+    pub settings: SharedSettings,
+}
+
+impl Default for GameOptions {
+    fn default() -> Self {
+        Self {
+            record_path: None,
+            replay: None,
+            seed: None,
+            arena: Arena::default(),
+            level: None,
+            mode: GameMode::Classic,
+            players: 1,
+            bots: Vec::new(),
+            net: None,
+            settings: SharedSettings::default(),
+        }
+    }
+}
+
+/// What the pause menu asked of the `GameState` underneath it. The game
+/// picks it up when it is resumed.
+pub enum PauseRequest {
+    Load(Snapshot),
+    Restart,
+}
+
+pub type PauseRequests = Rc<RefCell<Option<PauseRequest>>>;
+
+/// How many cells the camera shows around the arena.
+const ARENA_MARGIN: f32 = 5.;
+
+fn new_seed() -> u64 {
+    (date::now() * 1000.) as u64
+}
+
+fn camera_scale(arena: &Arena) -> f32 {
+    arena.height as f32 / 2. + ARENA_MARGIN
+}
+
+fn score_position(arena: &Arena) -> glam::Vec2 {
+    let (x, _) = arena.min();
+    let (_, y) = arena.max();
+    glam::Vec2::new(x as f32, y as f32 + 3.)
+}
+
+/// Colour of each player's snake, in player order.
+const PLAYER_COLORS: [graphics::Color; 4] = [
+    graphics::colors::RAYWHITE,
+    graphics::colors::SKYBLUE,
+    graphics::colors::LIME,
+    graphics::colors::ORANGE,
+];
+
+/// Colour of each kind of food.
+fn food_color(kind: FoodKind) -> graphics::Color {
+    match kind {
+        FoodKind::Plain => graphics::colors::PURPLE,
+        FoodKind::Golden => graphics::colors::GOLD,
+        FoodKind::Shrink => graphics::colors::BLUE,
+        FoodKind::Slow => graphics::colors::DARKGREEN,
+        FoodKind::Poison => graphics::colors::MAROON,
+    }
+}
+
+/// Builds the meshes the board is drawn with, once.
+fn load_assets(renderer: &mut graphics::MainRenderer) {
+    if renderer
+        .materials
+        .contains_key(&renderer::AssetIdentity::from("Snake"))
+    {
+        return;
+    }
+    let arrow_texture =
+        crate::utils::build_square_texture(&mut renderer.ctx, 4, crate::graphics::colors::RED);
+    let wall_texture =
+        crate::utils::build_square_texture(&mut renderer.ctx, 4, crate::graphics::colors::GRAY);
+
+    for (player, color) in PLAYER_COLORS.iter().enumerate() {
+        let player = components::Player(player as u8);
+        let snake_texture = crate::utils::build_square_texture(&mut renderer.ctx, 4, *color);
+        let tail_texture = crate::utils::build_square_texture(&mut renderer.ctx, 4, *color);
+        renderer.add_material(player.head_material(), vec![snake_texture]);
+        renderer.add_material(player.tail_material(), vec![tail_texture]);
+    }
+    for spec in FOOD_TABLE.iter() {
+        let food_texture =
+            crate::utils::build_square_texture(&mut renderer.ctx, 4, food_color(spec.kind));
+        renderer.add_material(spec.material, vec![food_texture]);
+    }
+    renderer.add_material("Arrow", vec![arrow_texture]);
+    renderer.add_material("Wall", vec![wall_texture]);
+
+    let snake_mesh = crate::utils::make_square_raw(1.);
+    let food_mesh = crate::utils::make_square_raw(0.8);
+    let tail_mesh = crate::utils::make_square_raw(0.8);
+    let arrow_mesh = crate::utils::make_arrow_raw();
+    let wall_mesh = crate::utils::make_square_raw(1.);
+
+    renderer.add_mesh("Food", &food_mesh.0, &food_mesh.1);
+    renderer.add_mesh("Tail", &tail_mesh.0, &tail_mesh.1);
+    renderer.add_mesh("Snake", &snake_mesh.0, &snake_mesh.1);
+    renderer.add_mesh("Arrow", &arrow_mesh.0, &arrow_mesh.1);
+    renderer.add_mesh("Wall", &wall_mesh.0, &wall_mesh.1);
+}
 
 pub struct GameState {
     direction: components::Direction,
     game_world: GameWorld,
-    move_timer: components::Timer,
-    food_timer: components::Timer,
-    score: i32,
+    camera: components::Camera2D,
+    clock: SimClock,
+    /// Indexed by player.
+    scores: Vec<i32>,
+    /// Turns pressed since the last tick, fed to the simulation one per tick.
+    pending_input: VecDeque<Input>,
+    /// `None` while the run started from a loaded snapshot, which a replay
+    /// can't reproduce from a seed.
+    recording: Option<Replay>,
+    record_path: Option<PathBuf>,
+    playback: Option<Playback>,
+    bots: Vec<Option<Bot>>,
+    controllers: Vec<Option<Box<dyn SnakeController>>>,
+    net_config: Option<NetConfig>,
+    /// `None` when playing locally.
+    net: Option<Lockstep>,
+    /// Set once the peer has been heard from and the run restarted with the
+    /// seed both sides agreed on.
+    joined: bool,
+    /// Set once a peer with other settings has been reported.
+    mismatch_reported: bool,
+    requests: PauseRequests,
+    settings: SharedSettings,
 }
 
 impl GameState {
-    pub fn new(input: &components::Input, renderer: &mut renderer::MainRenderer) -> Self {
-        let mut game_world = GameWorld {
-            events: Vec::with_capacity(32),
-            camera: components::Camera2D::new(input, 20.),
-            world: hecs::World::new(),
+    /// Length of a single simulation tick, in seconds.
+    pub const STEP: f64 = clock::STEP;
+
+    pub fn new(
+        input: &components::Input,
+        renderer: &mut renderer::MainRenderer,
+        options: GameOptions,
+    ) -> Self {
+        let playback = options.replay.map(Playback::new);
+        let (seed, arena, level, players, mode) = match &playback {
+            Some(playback) => (
+                playback.seed(),
+                playback.arena(),
+                playback.level().cloned(),
+                playback.players(),
+                playback.mode(),
+            ),
+            None => (
+                options.seed.unwrap_or_else(new_seed),
+                options.arena,
+                options.level,
+                options.players,
+                options.mode,
+            ),
         };
-        systems::create_snake_system(&mut game_world);
-        let (load_cmd, text_component) =
-            components::Text::create_text(format!("Score:  {}", 0).as_str());
+        // What the peer has to be playing too.
+        let settings = net::settings_hash(
+            mode,
+            &level.as_ref().map_or(arena, |level| level.arena),
+            2,
+            level.as_ref(),
+        );
+        let net = options
+            .net
+            .as_ref()
+            .and_then(|config| match config.open(seed, settings) {
+                Ok(lockstep) => Some(lockstep),
+                Err(err) => {
+                    eprintln!("Failed to open {}, playing locally: {}", config.local, err);
+                    None
+                }
+            });
+        let mut game_world = GameWorld::new(seed);
+        game_world.arena = arena;
+        if let Some(level) = level {
+            game_world.set_level(level);
+        }
+        // A level brings its own arena.
+        let arena = game_world.arena;
+        game_world.players = players;
+        game_world.set_mode(mode);
+        snake::systems::create_arena_system(&mut game_world);
+        snake::systems::create_snake_system(&mut game_world);
+        let scores = vec![0; players as usize];
+        let (load_cmd, text_component) = components::Text::create_text(
+            systems::score_text(&scores, systems::time_left(&game_world)).as_str(),
+        );
 
         game_world.world.spawn((
             components::Score,
-            components::Position(glam::Vec2::new(-24., 18.)),
+            components::Position(score_position(&arena)),
             text_component,
         ));
         renderer.asset_commands.push(load_cmd);
 
-        let snake_texture = crate::utils::build_square_texture(
-            &mut renderer.ctx,
-            4,
-            crate::graphics::colors::RAYWHITE,
+        load_assets(renderer);
+
+        let mut recording = Replay::new(seed, arena, players, mode);
+        recording.level = game_world.level.clone();
+        GameState {
+            direction: components::Direction::Up,
+            game_world,
+            camera: components::Camera2D::new(input, camera_scale(&arena)),
+            clock: SimClock::new(GameState::STEP, SystemTime),
+            scores,
+            pending_input: VecDeque::with_capacity(components::TurnQueue::CAPACITY),
+            recording: Some(recording),
+            record_path: options.record_path,
+            playback,
+            controllers: options
+                .bots
+                .iter()
+                .map(|bot| bot.map(|bot| bot.controller()))
+                .collect(),
+            bots: options.bots,
+            net_config: options.net,
+            net,
+            joined: false,
+            mismatch_reported: false,
+            requests: PauseRequests::default(),
+            settings: options.settings,
+        }
+    }
+
+    /// The input for the next tick. While a replay is playing the player is
+    /// ignored until it runs out. In a networked game there is nothing until
+    /// the peer's input for the tick has arrived.
+    fn next_input(&mut self) -> Option<Input> {
+        let live = self.pending_input.pop_front().unwrap_or_default();
+        if let Some(net) = &mut self.net {
+            net.push_local(&live);
+            return net.next_input();
+        }
+        let playback = match &mut self.playback {
+            Some(playback) => playback,
+            None => return Some(live),
+        };
+        match playback.next_input() {
+            Some(input) => Some(input),
+            None => {
+                self.playback = None;
+                Some(live)
+            }
+        }
+    }
+
+    /// Reads the peer's packets, and the first time they arrive starts the
+    /// run over with the seed both sides play with.
+    fn poll_net(&mut self, renderer: &mut graphics::MainRenderer) {
+        let net = match &mut self.net {
+            Some(net) => net,
+            None => return,
+        };
+        if let Err(err) = net.poll() {
+            eprintln!("Failed to read from peer: {}", err);
+        }
+        if net.mismatch() && !self.mismatch_reported {
+            self.mismatch_reported = true;
+            eprintln!("Peer started with another mode, arena or level, not joining");
+        }
+        let seed = match net.seed() {
+            Some(seed) if net.connected() && !self.joined => seed,
+            _ => return,
+        };
+        self.joined = true;
+        self.game_world.reseed(seed);
+        snake::systems::reset_system(&mut self.game_world);
+        self.game_world.events.clear();
+        self.scores.iter_mut().for_each(|score| *score = 0);
+        systems::update_score_text(
+            &mut self.game_world,
+            &self.scores,
+            &mut renderer.asset_commands,
+        );
+        self.recording = Some(self.new_recording(seed));
+    }
+
+    fn new_recording(&self, seed: u64) -> Replay {
+        let mut replay = Replay::new(
+            seed,
+            self.game_world.arena,
+            self.game_world.players,
+            self.game_world.mode,
         );
-        let tail_texture = crate::utils::build_square_texture(
-            &mut renderer.ctx,
-            4,
-            crate::graphics::colors::RAYWHITE,
+        replay.level = self.game_world.level.clone();
+        replay
+    }
+
+    fn save_recording(&self) {
+        if let (Some(path), Some(recording)) = (&self.record_path, &self.recording) {
+            if let Err(err) = recording.save(path) {
+                eprintln!("Failed to save replay to {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    fn finish_run(&mut self) {
+        self.save_recording();
+        let seed = self.game_world.rng.next_u64();
+        self.game_world.reseed(seed);
+        self.recording = Some(self.new_recording(seed));
+    }
+
+    /// Options for the run after this one, seeded from this run so a fixed
+    /// `--seed` keeps giving the same sequence of games.
+    fn next_options(&mut self) -> GameOptions {
+        GameOptions {
+            record_path: self.record_path.clone(),
+            replay: None,
+            seed: Some(self.game_world.rng.next_u64()),
+            arena: self.game_world.arena,
+            level: self.game_world.level.clone(),
+            mode: self.game_world.mode,
+            players: self.game_world.players,
+            bots: self.bots.clone(),
+            net: self.net_config.clone(),
+            settings: self.settings.clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot, renderer: &mut graphics::MainRenderer) {
+        self.scores = snapshot.restore(&mut self.game_world);
+        self.scores.resize(self.game_world.players as usize, 0);
+        systems::update_score_text(
+            &mut self.game_world,
+            &self.scores,
+            &mut renderer.asset_commands,
         );
-        let food_texture = crate::utils::build_square_texture(
-            &mut renderer.ctx,
-            4,
-            crate::graphics::colors::PURPLE,
+        self.pending_input.clear();
+        self.recording = None;
+        self.playback = None;
+    }
+
+    fn restart(&mut self, renderer: &mut graphics::MainRenderer) {
+        snake::systems::reset_system(&mut self.game_world);
+        self.game_world.events.clear();
+        self.scores.iter_mut().for_each(|score| *score = 0);
+        systems::update_score_text(
+            &mut self.game_world,
+            &self.scores,
+            &mut renderer.asset_commands,
         );
-        let arrow_texture =
-            crate::utils::build_square_texture(&mut renderer.ctx, 4, crate::graphics::colors::RED);
+        self.pending_input.clear();
+        self.playback = None;
+        self.finish_run();
+    }
 
-        renderer.add_material("Snake", vec![snake_texture]);
-        renderer.add_material("Food", vec![food_texture]);
-        renderer.add_material("Tail", vec![tail_texture]);
-        renderer.add_material("Arrow", vec![arrow_texture]);
+    fn send_net(&mut self) {
+        if let Some(net) = &mut self.net {
+            if let Err(err) = net.send() {
+                eprintln!("Failed to send to peer: {}", err);
+            }
+        }
+    }
 
-        let snake_mesh = crate::utils::make_square_raw(1.);
-        let food_mesh = crate::utils::make_square_raw(0.8);
-        let tail_mesh = crate::utils::make_square_raw(0.8);
-        let arrow_mesh = crate::utils::make_arrow_raw();
+    /// How far through the current move the snake is, counting the part of
+    /// a tick the clock has banked but not stepped yet.
+    fn render_alpha(&self) -> f32 {
+        let timer = &self.game_world.move_timer;
+        let elapsed = timer.elapsed() + self.clock.alpha() * self.clock.step();
+        (elapsed / timer.duration()).min(1.) as f32
+    }
 
-        renderer.add_mesh("Food", &food_mesh.0, &food_mesh.1);
-        renderer.add_mesh("Tail", &tail_mesh.0, &tail_mesh.1);
-        renderer.add_mesh("Snake", &snake_mesh.0, &snake_mesh.1);
-        renderer.add_mesh("Arrow", &arrow_mesh.0, &arrow_mesh.1);
+    /// Folds the bots' turns into `input` as if their players had pressed
+    /// them, so recordings play them back like any other turn. Keys pressed
+    /// for a bot's player are ignored.
+    fn steer_bots(&mut self, input: &Input) -> Input {
+        let mut input = *input;
+        for (player, controller) in self.controllers.iter_mut().enumerate() {
+            let controller = match controller {
+                Some(controller) => controller,
+                None => continue,
+            };
+            let player = components::Player(player as u8);
+            input.release_moves(player.0);
+            if let Some(direction) = controller.direction(&self.game_world, player) {
+                input.press_move(player.0, direction);
+            }
+        }
+        input
+    }
 
-        GameState {
-            direction: components::Direction::Up,
-            game_world,
-            move_timer: components::Timer::new(0.25),
-            food_timer: components::Timer::new(1.5),
-            score: 0,
+    /// Runs one tick, handing back how the run went if it ended.
+    fn fixed_update(
+        &mut self,
+        input: &Input,
+        renderer: &mut graphics::MainRenderer,
+    ) -> Option<RunStats> {
+        let input = &if self.playback.is_some() || self.net.is_some() {
+            *input
+        } else {
+            self.steer_bots(input)
+        };
+        if let Some(recording) = &mut self.recording {
+            recording.record(input);
+        }
+        let scores = self.scores.clone();
+        let time_left = systems::time_left(&self.game_world);
+        snake::systems::update_input(&mut self.game_world, input);
+        snake::systems::step_system(&mut self.game_world, self.clock.step());
+        snake::systems::despawn_food_system(&mut self.game_world);
+        snake::systems::update_score_system(&mut self.game_world, &mut self.scores);
+        snake::systems::speed_system(&mut self.game_world, &self.scores);
+        if scores != self.scores || time_left != systems::time_left(&self.game_world) {
+            systems::update_score_text(
+                &mut self.game_world,
+                &self.scores,
+                &mut renderer.asset_commands,
+            );
+        }
+        let stats = snake::systems::game_over_system(&self.game_world)
+            .map(|outcome| RunStats::capture(&self.game_world, outcome, &self.scores));
+        if stats.is_some() {
+            self.save_recording();
+        }
+
+        self.game_world.events.clear();
+        if let Some(net) = &mut self.net {
+            net.record_hash(net::state_hash(&self.game_world));
         }
+        stats
     }
 }
 
 impl Stage for GameState {
-    fn enter(&mut self, _: &mut graphics::MainRenderer) {
-        self.move_timer.resume();
-        self.food_timer.resume();
+    fn name(&self) -> &'static str {
+        "Game"
+    }
+
+    fn enter(&mut self, renderer: &mut graphics::MainRenderer) {
+        let request = self.requests.borrow_mut().take();
+        match request {
+            Some(PauseRequest::Load(snapshot)) => self.restore(&snapshot, renderer),
+            Some(PauseRequest::Restart) => self.restart(renderer),
+            None => {}
+        }
+        self.clock.resume();
     }
 
     fn exit(&mut self, _: &mut graphics::MainRenderer) {
-        self.move_timer.paused();
-        self.food_timer.paused();
+        self.clock.pause();
     }
 
     fn update(&mut self, input: &Input, renderer: &mut graphics::MainRenderer) -> NextStage {
         let input = input.clone();
         if input.resized {
             let Input { width, height, .. } = input;
-            self.game_world.camera.resize(width, height);
+            self.camera.resize(width, height);
         }
-        if input.pause {
-            return NextStage::Push(Box::new(Paused::new()));
+        if input.pressed(components::Action::Pause) {
+            let snapshot = Snapshot::capture(&self.game_world, &self.scores).ok();
+            let paused = Paused::new(
+                snapshot,
+                self.requests.clone(),
+                self.settings.clone(),
+                self.net.is_some(),
+            );
+            return NextStage::Push(Box::new(paused));
         }
         self.direction.update(&input);
-        systems::update_input(&mut self.game_world, &input);
-        if self.move_timer.finished() {
-            crate::systems::update_velocity_direction(&mut self.game_world);
-            systems::tail_movement_system(&mut self.game_world);
-            systems::head_collision_system(&mut self.game_world);
-            systems::handle_collision_system(&mut self.game_world);
-            systems::trigger_tail_spawn(&mut self.game_world);
-            systems::movement_system(&mut self.game_world);
-            systems::spawn_tail_system(&mut self.game_world);
-            self.move_timer.reset();
-        }
-        if self.food_timer.finished() {
-            systems::add_food_system(&mut self.game_world);
-            self.food_timer.reset();
-        }
-
-        systems::despawn_food_system(&mut self.game_world);
-        systems::update_score_system(
-            &mut self.game_world,
-            &mut self.score,
-            &mut renderer.asset_commands,
-        );
-        if systems::game_over_system(&mut self.game_world) {
-            self.move_timer.reset();
-            self.food_timer.reset();
+        if input.any_direction() && self.pending_input.len() < components::TurnQueue::CAPACITY {
+            self.pending_input.push_back(input);
+        }
+        self.poll_net(renderer);
+        for _ in 0..self.clock.advance() {
+            // Waiting on the peer. The ticks are dropped rather than caught
+            // up on later.
+            let next_input = match self.next_input() {
+                Some(next_input) => next_input,
+                None => break,
+            };
+            if let Some(stats) = self.fixed_update(&next_input, renderer) {
+                self.send_net();
+                let game_over = GameOver::new(&input, stats, self.next_options());
+                return NextStage::Replace(Box::new(game_over));
+            }
+        }
+        self.send_net();
+        if let Some(desync) = self.net.as_ref().and_then(Lockstep::desync) {
+            eprintln!(
+                "Lost sync with the peer at tick {}: {:016x} here, {:016x} there",
+                desync.tick, desync.local, desync.remote
+            );
+            return NextStage::Pop;
         }
-
-        self.game_world.events.clear();
         NextStage::Noop
     }
 
     fn draw(&mut self, renderer: &mut graphics::MainRenderer) {
-        renderer.update_view(&self.game_world.camera);
-        systems::gather_render_cmds(&mut self.game_world, renderer);
-        systems::debug_render_cmds(&mut self.game_world, renderer);
+        renderer.update_view(&self.camera);
+        let alpha = self.render_alpha();
+        systems::gather_render_cmds(&mut self.game_world, renderer, alpha);
+        if self.settings.borrow().show_direction {
+            systems::debug_render_cmds(&mut self.game_world, renderer);
+        }
         systems::draw_text(&mut self.game_world, renderer);
     }
 }
@@ -0,0 +1,107 @@
+use glam::Vec2;
+use snake::systems::{Outcome, RunStats};
+
+use crate::{
+    components::{Camera2D, Input},
+    graphics,
+    stages::{
+        menu::{self, Menu},
+        GameOptions, GameState, NextStage, Stage,
+    },
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum GameOverEntry {
+    Retry,
+    Menu,
+}
+
+/// Takes the place of a finished `GameState` and shows how the run went.
+pub struct GameOver {
+    menu: Menu<GameOverEntry>,
+    camera: Camera2D,
+    title: String,
+    lines: Vec<String>,
+    /// What the next game is started with if the player retries.
+    options: GameOptions,
+}
+
+impl GameOver {
+    pub fn new(input: &Input, stats: RunStats, options: GameOptions) -> Self {
+        let menu = Menu::new(vec![
+            (GameOverEntry::Retry, "Retry".to_string()),
+            (GameOverEntry::Menu, "Menu".to_string()),
+        ]);
+        let title = match stats.outcome {
+            Outcome::Lost => "Game Over".to_string(),
+            Outcome::Won => "You Win".to_string(),
+            Outcome::Winner(player) => format!("Player {} Wins", player.0 + 1),
+            Outcome::Draw => "Draw".to_string(),
+            Outcome::TimeUp => "Time's Up".to_string(),
+        };
+        let mut lines = match stats.players.as_slice() {
+            [player] => vec![
+                format!("Score: {}", player.score),
+                format!("Length: {}", player.length),
+            ],
+            players => players
+                .iter()
+                .map(|player| {
+                    format!(
+                        "P{}: score {} length {}",
+                        player.player.0 + 1,
+                        player.score,
+                        player.length
+                    )
+                })
+                .collect(),
+        };
+        lines.push(format!("Time: {:.1}s", stats.time));
+        Self {
+            menu,
+            camera: Camera2D::new(input, menu::CAMERA_SCALE),
+            title,
+            lines,
+            options,
+        }
+    }
+}
+
+impl Stage for GameOver {
+    fn name(&self) -> &'static str {
+        "GameOver"
+    }
+
+    fn enter(&mut self, renderer: &mut graphics::MainRenderer) {
+        menu::load_assets(renderer);
+        menu::load_text(renderer, &self.title);
+        for line in self.lines.iter() {
+            menu::load_text(renderer, line);
+        }
+        self.menu.load(renderer);
+    }
+
+    fn update(&mut self, input: &Input, renderer: &mut graphics::MainRenderer) -> NextStage {
+        if input.resized {
+            self.camera.resize(input.width, input.height);
+        }
+        match self.menu.update(input) {
+            Some(GameOverEntry::Retry) => {
+                let options = std::mem::take(&mut self.options);
+                NextStage::Replace(Box::new(GameState::new(input, renderer, options)))
+            }
+            Some(GameOverEntry::Menu) => NextStage::Pop,
+            None => NextStage::Noop,
+        }
+    }
+
+    fn draw(&mut self, renderer: &mut graphics::MainRenderer) {
+        renderer.update_view(&self.camera);
+        menu::draw_text(renderer, &self.title, Vec2::new(-4., 12.));
+        for (index, line) in self.lines.iter().enumerate() {
+            let position = Vec2::new(-4., 8. - index as f32 * 2.5);
+            menu::draw_text(renderer, line, position);
+        }
+        self.menu.draw(renderer, Vec2::new(-4., -2.));
+    }
+}
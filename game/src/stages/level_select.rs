@@ -0,0 +1,127 @@
+use glam::Vec2;
+use snake::level::Level;
+
+use crate::{
+    components::{Action, Camera2D, Input},
+    graphics,
+    stages::{
+        menu::{self, Menu},
+        GameOptions, GameState, ModeSelect, NextStage, Stage,
+    },
+};
+
+/// Where the levels to pick from are kept.
+pub const LEVELS_DIR: &str = "levels";
+
+const TITLE: &str = "Level";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum LevelEntry {
+    /// The plain arena, with no level.
+    Open,
+    /// Indexes `LevelSelect::levels`.
+    Level(usize),
+    Back,
+}
+
+/// Every level in `LEVELS_DIR` that `players` snakes can play, sorted by
+/// file name. Levels that can't be read or played are reported and left out.
+pub fn playable_levels(players: u8) -> Vec<Level> {
+    let levels = match Level::load_dir(LEVELS_DIR) {
+        Ok(levels) => levels,
+        // No levels folder just means there are no levels.
+        Err(_) => return Vec::new(),
+    };
+    levels
+        .into_iter()
+        .filter_map(|(path, level)| {
+            match level.and_then(|level| level.validate(players).map(|_| level)) {
+                Ok(level) => Some(level),
+                Err(err) => {
+                    eprintln!("Skipping level {}: {}", path.display(), err);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Picks the level a game is played on, once its mode is picked. Takes the
+/// place of the `ModeSelect` before it, and of itself with the game.
+pub struct LevelSelect {
+    menu: Menu<LevelEntry>,
+    camera: Camera2D,
+    levels: Vec<Level>,
+    /// Everything about the game but its level.
+    options: GameOptions,
+}
+
+impl LevelSelect {
+    pub fn new(input: &Input, options: GameOptions, levels: Vec<Level>) -> Self {
+        let mut entries = vec![(LevelEntry::Open, "Open Arena".to_string())];
+        entries.extend(
+            levels
+                .iter()
+                .enumerate()
+                .map(|(index, level)| (LevelEntry::Level(index), level.name.clone())),
+        );
+        entries.push((LevelEntry::Back, "Back".to_string()));
+        Self {
+            menu: Menu::new(entries),
+            camera: Camera2D::new(input, menu::CAMERA_SCALE),
+            levels,
+            options,
+        }
+    }
+
+    fn start(
+        &self,
+        input: &Input,
+        renderer: &mut graphics::MainRenderer,
+        level: Option<Level>,
+    ) -> NextStage {
+        let mut options = self.options.clone();
+        options.level = level;
+        let game = GameState::new(input, renderer, options);
+        NextStage::Replace(Box::new(game))
+    }
+
+    /// Goes back to picking the mode, which starts on the one picked before.
+    fn back(&self, input: &Input) -> NextStage {
+        NextStage::Replace(Box::new(ModeSelect::new(input, self.options.clone())))
+    }
+}
+
+impl Stage for LevelSelect {
+    fn name(&self) -> &'static str {
+        "LevelSelect"
+    }
+
+    fn enter(&mut self, renderer: &mut graphics::MainRenderer) {
+        menu::load_assets(renderer);
+        menu::load_text(renderer, TITLE);
+        self.menu.load(renderer);
+    }
+
+    fn update(&mut self, input: &Input, renderer: &mut graphics::MainRenderer) -> NextStage {
+        self.camera.resize(input.width, input.height);
+        if input.pressed(Action::Back) {
+            return self.back(input);
+        }
+        match self.menu.update(input) {
+            Some(LevelEntry::Open) => self.start(input, renderer, None),
+            Some(LevelEntry::Level(index)) => {
+                let level = self.levels[index].clone();
+                self.start(input, renderer, Some(level))
+            }
+            Some(LevelEntry::Back) => self.back(input),
+            None => NextStage::Noop,
+        }
+    }
+
+    fn draw(&mut self, renderer: &mut graphics::MainRenderer) {
+        renderer.update_view(&self.camera);
+        menu::draw_text(renderer, TITLE, Vec2::new(-4., 9.));
+        self.menu.draw(renderer, Vec2::new(-4., 5.));
+    }
+}
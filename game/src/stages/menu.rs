@@ -0,0 +1,152 @@
+use glam::Vec2;
+
+use crate::{
+    components::{Action, Input},
+    graphics::{self, colors, renderer},
+};
+use renderer::AssetIdentity;
+
+const FONT: &str = "KenneyFuture";
+const LINE_HEIGHT: f32 = 2.5;
+
+/// Half the height, in world units, of the camera menus that fill the screen
+/// on their own are laid out for.
+pub const CAMERA_SCALE: f32 = 20.;
+
+/// A vertical list of labelled entries. Up and down move the selection and
+/// confirm picks the selected entry.
+pub struct Menu<T: Copy> {
+    entries: Vec<(T, String)>,
+    selected: usize,
+}
+
+impl<T: Copy> Menu<T> {
+    pub fn new(entries: Vec<(T, String)>) -> Self {
+        Self {
+            entries,
+            selected: 0,
+        }
+    }
+
+    pub fn selected(&self) -> T {
+        self.entries[self.selected].0
+    }
+
+    /// Queues every label for upload, the renderer can only draw text it has
+    /// already built buffers for.
+    pub fn load(&self, renderer: &mut graphics::MainRenderer) {
+        for (_, label) in self.entries.iter() {
+            load_text(renderer, label);
+        }
+    }
+
+    /// Moves the selection to `entry`, if the menu has it.
+    pub fn select(&mut self, entry: T)
+    where
+        T: PartialEq,
+    {
+        if let Some(index) = self.entries.iter().position(|(e, _)| *e == entry) {
+            self.selected = index;
+        }
+    }
+
+    pub fn set_label(&mut self, entry: T, label: String, renderer: &mut graphics::MainRenderer)
+    where
+        T: PartialEq,
+    {
+        load_text(renderer, &label);
+        if let Some((_, old)) = self.entries.iter_mut().find(|(e, _)| *e == entry) {
+            *old = label;
+        }
+    }
+
+    pub fn update(&mut self, input: &Input) -> Option<T> {
+        let len = self.entries.len();
+        if input.pressed(Action::MoveUp) {
+            self.selected = (self.selected + len - 1) % len;
+        }
+        if input.pressed(Action::MoveDown) {
+            self.selected = (self.selected + 1) % len;
+        }
+        if input.pressed(Action::Confirm) {
+            return Some(self.selected());
+        }
+        None
+    }
+
+    /// Draws the entries downwards from `origin`, with a marker next to the
+    /// selected one.
+    pub fn draw(&self, renderer: &mut graphics::MainRenderer, origin: Vec2) {
+        for (index, (_, label)) in self.entries.iter().enumerate() {
+            let position = origin - Vec2::new(0., index as f32 * LINE_HEIGHT);
+            draw_text(renderer, label, position);
+            if index == self.selected {
+                renderer
+                    .main_render_target
+                    .commands
+                    .push(renderer::RenderCommand::DrawMesh2D(renderer::DrawMesh2D {
+                        material: "MenuMarker".into(),
+                        mesh: "MenuMarker".into(),
+                        position: position - Vec2::new(1.5, 0.),
+                        rotation: 0.,
+                    }));
+            }
+        }
+    }
+}
+
+pub fn load_text(renderer: &mut graphics::MainRenderer, text: &str) {
+    renderer
+        .asset_commands
+        .push(renderer::RenderAssetCommands::LoadText {
+            text: text.to_owned(),
+            font: FONT.to_owned(),
+        });
+}
+
+pub fn draw_text(renderer: &mut graphics::MainRenderer, text: &str, position: Vec2) {
+    renderer
+        .main_render_target
+        .commands
+        .push(renderer::RenderCommand::DrawFont(renderer::DrawFont {
+            text: text.to_owned(),
+            font: FONT.into(),
+            position,
+        }));
+}
+
+/// Builds the meshes menus draw with, once.
+pub fn load_assets(renderer: &mut graphics::MainRenderer) {
+    if renderer
+        .materials
+        .contains_key(&AssetIdentity::from("Overlay"))
+    {
+        return;
+    }
+    let overlay_texture = crate::utils::build_square_texture(
+        &mut renderer.ctx,
+        4,
+        graphics::Color::new(0., 0., 0., 0.6),
+    );
+    let marker_texture = crate::utils::build_square_texture(&mut renderer.ctx, 4, colors::GOLD);
+    renderer.add_material("Overlay", vec![overlay_texture]);
+    renderer.add_material("MenuMarker", vec![marker_texture]);
+
+    let overlay_mesh = crate::utils::make_square_raw(200.);
+    let marker_mesh = crate::utils::make_square_raw(0.8);
+    renderer.add_mesh("Overlay", &overlay_mesh.0, &overlay_mesh.1);
+    renderer.add_mesh("MenuMarker", &marker_mesh.0, &marker_mesh.1);
+}
+
+/// Dims whatever was drawn before it.
+pub fn draw_overlay(renderer: &mut graphics::MainRenderer) {
+    renderer
+        .main_render_target
+        .commands
+        .push(renderer::RenderCommand::DrawMesh2D(renderer::DrawMesh2D {
+            material: "Overlay".into(),
+            mesh: "Overlay".into(),
+            position: Vec2::new(0., 0.),
+            rotation: 0.,
+        }));
+}
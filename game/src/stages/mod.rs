@@ -1,25 +1,65 @@
+pub mod controls;
 pub mod game;
+pub mod game_over;
+pub mod level_select;
+pub mod menu;
+pub mod mode_select;
 pub mod paused;
+pub mod settings;
+pub mod stack;
+pub mod title;
 
 use crate::{components::Input, graphics};
-pub use game::GameState;
+pub use controls::Controls;
+pub use game::{GameOptions, GameState};
+pub use game_over::GameOver;
+pub use level_select::LevelSelect;
+pub use mode_select::ModeSelect;
 pub use paused::Paused;
+pub use settings::SettingsMenu;
+pub use stack::StageStack;
+pub use title::Title;
 
-pub type StageStack = Vec<Box<dyn Stage>>;
-
-pub fn new_stage_stack(capacity: usize) -> StageStack {
-    Vec::with_capacity(capacity)
-}
-
-pub enum NextStage {
+/// What the top stage wants done to the stack after its update. See
+/// `StageStack::apply` for the order stages are exited and entered in.
+pub enum NextStage<R = graphics::MainRenderer> {
     Noop,
     Pop,
-    Push(Box<dyn Stage>),
+    Push(Box<dyn Stage<R>>),
+    /// Pops the current stage and pushes this one in its place. The stage
+    /// underneath is not entered in between.
+    Replace(Box<dyn Stage<R>>),
+    /// Pops this many stages, or every stage if there are fewer.
+    PopN(usize),
+    /// Pops stages until the top one matches. Does nothing if none do.
+    PopTo(Box<dyn Fn(&dyn Stage<R>) -> bool>),
+    /// Drops every stage and starts over with this one.
+    Clear(Box<dyn Stage<R>>),
 }
 
-pub trait Stage {
-    fn enter(&mut self, _renderer: &mut graphics::MainRenderer) {}
-    fn exit(&mut self, _renderer: &mut graphics::MainRenderer) {}
-    fn update(&mut self, input: &Input, _renderer: &mut graphics::MainRenderer) -> NextStage;
-    fn draw(&mut self, _renderer: &mut graphics::MainRenderer) {}
+/// A screen the game can be on. The top of the `StageStack` is always
+/// updated and drawn, and what is below it depends on the policies here.
+///
+/// `R` is whatever the stages load and draw with, the `MainRenderer` in the
+/// game itself.
+pub trait Stage<R = graphics::MainRenderer> {
+    /// Names the stage for `NextStage::PopTo`.
+    fn name(&self) -> &'static str;
+    /// Whether the stages below show through this one and should be drawn
+    /// first. Stages that cover the whole screen leave this `false`.
+    fn is_transparent(&self) -> bool {
+        false
+    }
+    /// Whether the stages below stop updating while this one is above them.
+    /// Notifications and HUDs that shouldn't pause the game return `false`.
+    fn blocks_update(&self) -> bool {
+        true
+    }
+    /// Called when the stage starts being updated.
+    fn enter(&mut self, _renderer: &mut R) {}
+    /// Called when the stage stops being updated, whether it is covered or
+    /// removed.
+    fn exit(&mut self, _renderer: &mut R) {}
+    fn update(&mut self, input: &Input, _renderer: &mut R) -> NextStage<R>;
+    fn draw(&mut self, _renderer: &mut R) {}
 }
@@ -0,0 +1,102 @@
+use glam::Vec2;
+use snake::mode::GameMode;
+
+use crate::{
+    components::{Action, Camera2D, Input},
+    graphics,
+    stages::{
+        level_select,
+        menu::{self, Menu},
+        GameOptions, GameState, LevelSelect, NextStage, Stage,
+    },
+};
+
+const TITLE: &str = "Mode";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ModeEntry {
+    Mode(GameMode),
+    Back,
+}
+
+/// Picks the mode a game is played in before it starts. Takes its own place
+/// on the stack with the game, or with `LevelSelect` when there are levels
+/// to pick from, so quitting the game goes back to the title.
+pub struct ModeSelect {
+    menu: Menu<ModeEntry>,
+    camera: Camera2D,
+    /// Everything about the game but its mode.
+    options: GameOptions,
+}
+
+impl ModeSelect {
+    pub fn new(input: &Input, options: GameOptions) -> Self {
+        let mut entries: Vec<(ModeEntry, String)> = GameMode::ALL
+            .iter()
+            .map(|mode| (ModeEntry::Mode(*mode), mode.label().to_string()))
+            .collect();
+        entries.push((ModeEntry::Back, "Back".to_string()));
+        // Starts on the mode given on the command line.
+        let mut modes = Menu::new(entries);
+        modes.select(ModeEntry::Mode(options.mode));
+        Self {
+            menu: modes,
+            camera: Camera2D::new(input, menu::CAMERA_SCALE),
+            options,
+        }
+    }
+}
+
+impl Stage for ModeSelect {
+    fn name(&self) -> &'static str {
+        "ModeSelect"
+    }
+
+    fn enter(&mut self, renderer: &mut graphics::MainRenderer) {
+        menu::load_assets(renderer);
+        menu::load_text(renderer, TITLE);
+        self.menu.load(renderer);
+    }
+
+    fn update(&mut self, input: &Input, renderer: &mut graphics::MainRenderer) -> NextStage {
+        self.camera.resize(input.width, input.height);
+        if input.pressed(Action::Back) {
+            return NextStage::Pop;
+        }
+        match self.menu.update(input) {
+            Some(ModeEntry::Mode(mode)) => {
+                let mut options = self.options.clone();
+                options.mode = mode;
+                // A level given on the command line is played without asking,
+                // as long as it fits this many players.
+                if let Some(level) = &options.level {
+                    if let Err(err) = level.validate(options.players) {
+                        eprintln!(
+                            "Can't play {} with {} players: {}",
+                            level.name, options.players, err
+                        );
+                        options.level = None;
+                    }
+                }
+                let levels = match options.level {
+                    Some(_) => Vec::new(),
+                    None => level_select::playable_levels(options.players),
+                };
+                if levels.is_empty() {
+                    let game = GameState::new(input, renderer, options);
+                    NextStage::Replace(Box::new(game))
+                } else {
+                    NextStage::Replace(Box::new(LevelSelect::new(input, options, levels)))
+                }
+            }
+            Some(ModeEntry::Back) => NextStage::Pop,
+            None => NextStage::Noop,
+        }
+    }
+
+    fn draw(&mut self, renderer: &mut graphics::MainRenderer) {
+        renderer.update_view(&self.camera);
+        menu::draw_text(renderer, TITLE, Vec2::new(-4., 9.));
+        self.menu.draw(renderer, Vec2::new(-4., 5.));
+    }
+}
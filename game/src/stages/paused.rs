@@ -1,25 +1,138 @@
+use glam::Vec2;
+use snake::snapshot::Snapshot;
+
 use crate::{
-    components::Input,
+    components::{Action, Input},
     graphics,
-    stages::{NextStage, Stage},
+    settings::SharedSettings,
+    stages::{
+        game::{PauseRequest, PauseRequests},
+        menu::{self, Menu},
+        NextStage, SettingsMenu, Stage, Title,
+    },
 };
 
-#[derive(Debug, Copy, Clone, Default)]
+/// Where the pause menu saves to and loads from.
+pub const SAVE_PATH: &str = "snek.save";
+
+const TITLE: &str = "Paused";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum PauseEntry {
+    Resume,
+    Restart,
+    Save,
+    Load,
+    Settings,
+    Quit,
+}
+
 pub struct Paused {
-    go_back: bool,
+    menu: Menu<PauseEntry>,
+    snapshot: Option<Snapshot>,
+    requests: PauseRequests,
+    settings: SharedSettings,
 }
 
 impl Paused {
-    pub fn new() -> Self {
-        Default::default()
+    /// `snapshot` is the game as it was when it was paused. Anything the game
+    /// has to do once it resumes, like loading a save, goes in `requests`.
+    /// A `networked` game can't restart, save or load, the peer would play
+    /// on without it, so those entries are left out.
+    pub fn new(
+        snapshot: Option<Snapshot>,
+        requests: PauseRequests,
+        settings: SharedSettings,
+        networked: bool,
+    ) -> Self {
+        let mut entries = vec![(PauseEntry::Resume, "Resume".to_string())];
+        if !networked {
+            entries.extend(vec![
+                (PauseEntry::Restart, "Restart".to_string()),
+                (PauseEntry::Save, "Save".to_string()),
+                (PauseEntry::Load, "Load".to_string()),
+            ]);
+        }
+        entries.extend(vec![
+            (PauseEntry::Settings, "Settings".to_string()),
+            (PauseEntry::Quit, "Quit to Title".to_string()),
+        ]);
+        let menu = Menu::new(entries);
+        Self {
+            menu,
+            snapshot,
+            requests,
+            settings,
+        }
+    }
+
+    fn request(&self, request: PauseRequest) -> NextStage {
+        *self.requests.borrow_mut() = Some(request);
+        NextStage::Pop
+    }
+
+    fn save(&self) {
+        let snapshot = match &self.snapshot {
+            Some(snapshot) => snapshot,
+            _ => return,
+        };
+        if let Err(err) = snapshot.save(SAVE_PATH) {
+            eprintln!("Failed to save game to {}: {}", SAVE_PATH, err);
+        }
+    }
+
+    fn load(&self) -> NextStage {
+        match Snapshot::load(SAVE_PATH) {
+            Ok(snapshot) => self.request(PauseRequest::Load(snapshot)),
+            Err(err) => {
+                eprintln!("Failed to load game from {}: {}", SAVE_PATH, err);
+                NextStage::Noop
+            }
+        }
     }
 }
 
 impl Stage for Paused {
+    fn name(&self) -> &'static str {
+        "Paused"
+    }
+
+    /// Drawn as an overlay on top of whatever it was opened from.
+    fn is_transparent(&self) -> bool {
+        true
+    }
+
+    fn enter(&mut self, renderer: &mut graphics::MainRenderer) {
+        menu::load_assets(renderer);
+        menu::load_text(renderer, TITLE);
+        self.menu.load(renderer);
+    }
+
     fn update(&mut self, input: &Input, _renderer: &mut graphics::MainRenderer) -> NextStage {
-        if input.go_back {
+        if input.pressed(Action::Back) {
             return NextStage::Pop;
         }
-        NextStage::Noop
+        match self.menu.update(input) {
+            Some(PauseEntry::Resume) => NextStage::Pop,
+            Some(PauseEntry::Restart) => self.request(PauseRequest::Restart),
+            Some(PauseEntry::Save) => {
+                self.save();
+                NextStage::Noop
+            }
+            Some(PauseEntry::Load) => self.load(),
+            Some(PauseEntry::Settings) => {
+                NextStage::Push(Box::new(SettingsMenu::new(self.settings.clone())))
+            }
+            Some(PauseEntry::Quit) => {
+                NextStage::PopTo(Box::new(|stage: &dyn Stage| stage.name() == Title::NAME))
+            }
+            None => NextStage::Noop,
+        }
+    }
+
+    fn draw(&mut self, renderer: &mut graphics::MainRenderer) {
+        menu::draw_overlay(renderer);
+        menu::draw_text(renderer, TITLE, Vec2::new(-4., 9.));
+        self.menu.draw(renderer, Vec2::new(-4., 5.));
     }
 }
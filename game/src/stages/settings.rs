@@ -0,0 +1,90 @@
+use glam::Vec2;
+
+use crate::{
+    components::{Action, Input},
+    graphics,
+    settings::SharedSettings,
+    stages::{
+        menu::{self, Menu},
+        Controls, NextStage, Stage,
+    },
+};
+
+const TITLE: &str = "Settings";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum SettingsEntry {
+    Direction,
+    Controls,
+    Back,
+}
+
+pub struct SettingsMenu {
+    menu: Menu<SettingsEntry>,
+    settings: SharedSettings,
+}
+
+fn direction_label(on: bool) -> String {
+    format!("Direction Arrow: {}", if on { "On" } else { "Off" })
+}
+
+impl SettingsMenu {
+    pub fn new(settings: SharedSettings) -> Self {
+        let show_direction = settings.borrow().show_direction;
+        let menu = Menu::new(vec![
+            (SettingsEntry::Direction, direction_label(show_direction)),
+            (SettingsEntry::Controls, "Controls".to_string()),
+            (SettingsEntry::Back, "Back".to_string()),
+        ]);
+        Self { menu, settings }
+    }
+}
+
+impl Stage for SettingsMenu {
+    fn name(&self) -> &'static str {
+        "Settings"
+    }
+
+    /// Drawn as an overlay on top of whatever it was opened from.
+    fn is_transparent(&self) -> bool {
+        true
+    }
+
+    fn enter(&mut self, renderer: &mut graphics::MainRenderer) {
+        menu::load_assets(renderer);
+        menu::load_text(renderer, TITLE);
+        self.menu.load(renderer);
+    }
+
+    fn update(&mut self, input: &Input, renderer: &mut graphics::MainRenderer) -> NextStage {
+        if input.pressed(Action::Back) {
+            return NextStage::Pop;
+        }
+        match self.menu.update(input) {
+            Some(SettingsEntry::Direction) => {
+                let show_direction = {
+                    let mut settings = self.settings.borrow_mut();
+                    settings.show_direction = !settings.show_direction;
+                    settings.show_direction
+                };
+                self.menu.set_label(
+                    SettingsEntry::Direction,
+                    direction_label(show_direction),
+                    renderer,
+                );
+                NextStage::Noop
+            }
+            Some(SettingsEntry::Controls) => {
+                NextStage::Push(Box::new(Controls::new(self.settings.clone())))
+            }
+            Some(SettingsEntry::Back) => NextStage::Pop,
+            None => NextStage::Noop,
+        }
+    }
+
+    fn draw(&mut self, renderer: &mut graphics::MainRenderer) {
+        menu::draw_overlay(renderer);
+        menu::draw_text(renderer, TITLE, Vec2::new(-4., 9.));
+        self.menu.draw(renderer, Vec2::new(-4., 5.));
+    }
+}
@@ -0,0 +1,342 @@
+use crate::{
+    components::Input,
+    graphics,
+    stages::{NextStage, Stage},
+};
+
+struct Entry<R> {
+    stage: Box<dyn Stage<R>>,
+    /// Whether the stage has been entered and not exited since.
+    active: bool,
+}
+
+/// The stages the game is made of, the top one being the one in control.
+///
+/// The top stage is always active, and so is every stage below an unbroken
+/// run of stages that don't block updates. After every transition stages
+/// that are no longer active are exited, top first, and only then are the
+/// newly active ones entered, bottom first. Stages removed while active are
+/// exited before they are dropped.
+pub struct StageStack<R = graphics::MainRenderer> {
+    stages: Vec<Entry<R>>,
+}
+
+impl<R> StageStack<R> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            stages: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    pub fn top(&self) -> Option<&dyn Stage<R>> {
+        self.stages.last().map(|entry| entry.stage.as_ref())
+    }
+
+    /// Names of every stage, bottom first.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|entry| entry.stage.name()).collect()
+    }
+
+    pub fn push(&mut self, stage: Box<dyn Stage<R>>, renderer: &mut R) {
+        self.apply(NextStage::Push(stage), renderer);
+    }
+
+    /// Updates the active stages, top first, and carries out the first
+    /// transition one of them asks for. A transition from a stage below the
+    /// top pops the stages above it before it is applied.
+    pub fn update(&mut self, input: &Input, renderer: &mut R) {
+        let mut index = self.stages.len();
+        while index > 0 {
+            index -= 1;
+            let entry = &mut self.stages[index];
+            if !entry.active {
+                break;
+            }
+            match entry.stage.update(input, renderer) {
+                NextStage::Noop => {}
+                next => {
+                    let above = self.stages.len() - index - 1;
+                    self.pop(above, renderer);
+                    self.apply(next, renderer);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Draws the top stage and whatever shows through it, bottom first.
+    pub fn draw(&mut self, renderer: &mut R) {
+        let mut bottom = self.stages.len();
+        while bottom > 0 {
+            bottom -= 1;
+            if !self.stages[bottom].stage.is_transparent() {
+                break;
+            }
+        }
+        for entry in self.stages.iter_mut().skip(bottom) {
+            entry.stage.draw(renderer);
+        }
+    }
+
+    pub fn apply(&mut self, next: NextStage<R>, renderer: &mut R) {
+        match next {
+            NextStage::Noop => {}
+            NextStage::Push(stage) => self.insert(stage, renderer),
+            NextStage::Pop => self.pop(1, renderer),
+            NextStage::PopN(count) => self.pop(count, renderer),
+            NextStage::Replace(stage) => {
+                self.remove(1, renderer);
+                self.insert(stage, renderer);
+            }
+            NextStage::PopTo(predicate) => {
+                let target = self
+                    .stages
+                    .iter()
+                    .rposition(|entry| predicate(entry.stage.as_ref()));
+                if let Some(index) = target {
+                    self.pop(self.stages.len() - index - 1, renderer);
+                }
+            }
+            NextStage::Clear(stage) => {
+                self.remove(self.stages.len(), renderer);
+                self.insert(stage, renderer);
+            }
+        }
+    }
+
+    fn insert(&mut self, stage: Box<dyn Stage<R>>, renderer: &mut R) {
+        self.stages.push(Entry {
+            stage,
+            active: false,
+        });
+        self.sync(renderer);
+    }
+
+    fn pop(&mut self, count: usize, renderer: &mut R) {
+        if count == 0 {
+            return;
+        }
+        self.remove(count, renderer);
+        self.sync(renderer);
+    }
+
+    /// Takes up to `count` stages off the top, exiting the active ones.
+    fn remove(&mut self, count: usize, renderer: &mut R) {
+        let len = self.stages.len().saturating_sub(count);
+        while self.stages.len() > len {
+            let mut entry = self.stages.pop().expect("Stack is longer than len");
+            if entry.active {
+                entry.stage.exit(renderer);
+            }
+        }
+    }
+
+    /// Exits and enters stages so that exactly the top and the stages it lets
+    /// update through are active.
+    fn sync(&mut self, renderer: &mut R) {
+        let mut active = vec![false; self.stages.len()];
+        for index in (0..self.stages.len()).rev() {
+            active[index] = true;
+            if self.stages[index].stage.blocks_update() {
+                break;
+            }
+        }
+        for (entry, active) in self.stages.iter_mut().zip(active.iter()).rev() {
+            if entry.active && !active {
+                entry.stage.exit(renderer);
+                entry.active = false;
+            }
+        }
+        for (entry, active) in self.stages.iter_mut().zip(active.iter()) {
+            if !entry.active && *active {
+                entry.stage.enter(renderer);
+                entry.active = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for the renderer, collecting what the stages were asked to do.
+    type Log = Vec<String>;
+
+    struct Mock {
+        name: &'static str,
+        blocks: bool,
+        /// Asked for on the next update.
+        next: Option<NextStage<Log>>,
+    }
+
+    impl Stage<Log> for Mock {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn blocks_update(&self) -> bool {
+            self.blocks
+        }
+
+        fn enter(&mut self, log: &mut Log) {
+            log.push(format!("enter {}", self.name));
+        }
+
+        fn exit(&mut self, log: &mut Log) {
+            log.push(format!("exit {}", self.name));
+        }
+
+        fn update(&mut self, _input: &Input, log: &mut Log) -> NextStage<Log> {
+            log.push(format!("update {}", self.name));
+            self.next.take().unwrap_or(NextStage::Noop)
+        }
+    }
+
+    fn stage(name: &'static str) -> Box<dyn Stage<Log>> {
+        Box::new(Mock {
+            name,
+            blocks: true,
+            next: None,
+        })
+    }
+
+    fn overlay(name: &'static str) -> Box<dyn Stage<Log>> {
+        Box::new(Mock {
+            name,
+            blocks: false,
+            next: None,
+        })
+    }
+
+    fn asking(name: &'static str, next: NextStage<Log>) -> Box<dyn Stage<Log>> {
+        Box::new(Mock {
+            name,
+            blocks: true,
+            next: Some(next),
+        })
+    }
+
+    /// A stack of `names`, pushed in order, with the log of doing so cleared.
+    fn stack_of(names: &[&'static str], log: &mut Log) -> StageStack<Log> {
+        let mut stack = StageStack::with_capacity(names.len());
+        for name in names {
+            stack.push(stage(*name), log);
+        }
+        log.clear();
+        stack
+    }
+
+    #[test]
+    fn push_exits_the_stage_below_before_entering() {
+        let mut log = Log::new();
+        let mut stack = stack_of(&["title"], &mut log);
+        stack.push(stage("game"), &mut log);
+        assert_eq!(stack.names(), vec!["title", "game"]);
+        assert_eq!(log, vec!["exit title", "enter game"]);
+    }
+
+    #[test]
+    fn push_of_an_overlay_keeps_the_stage_below_active() {
+        let mut log = Log::new();
+        let mut stack = stack_of(&["game"], &mut log);
+        stack.push(overlay("hud"), &mut log);
+        assert_eq!(log, vec!["enter hud"]);
+
+        log.clear();
+        stack.update(&Input::default(), &mut log);
+        assert_eq!(log, vec!["update hud", "update game"]);
+    }
+
+    #[test]
+    fn pop_exits_the_top_and_enters_the_one_below() {
+        let mut log = Log::new();
+        let mut stack = stack_of(&["title", "game"], &mut log);
+        stack.apply(NextStage::Pop, &mut log);
+        assert_eq!(stack.names(), vec!["title"]);
+        assert_eq!(log, vec!["exit game", "enter title"]);
+    }
+
+    #[test]
+    fn replace_skips_entering_the_stage_underneath() {
+        let mut log = Log::new();
+        let mut stack = stack_of(&["title", "modes"], &mut log);
+        stack.apply(NextStage::Replace(stage("game")), &mut log);
+        assert_eq!(stack.names(), vec!["title", "game"]);
+        assert_eq!(log, vec!["exit modes", "enter game"]);
+    }
+
+    #[test]
+    fn pop_n_pops_that_many_or_everything() {
+        let mut log = Log::new();
+        let mut stack = stack_of(&["title", "game", "paused"], &mut log);
+        stack.apply(NextStage::PopN(2), &mut log);
+        assert_eq!(stack.names(), vec!["title"]);
+        assert_eq!(log, vec!["exit paused", "enter title"]);
+
+        log.clear();
+        stack.apply(NextStage::PopN(5), &mut log);
+        assert!(stack.is_empty());
+        assert_eq!(log, vec!["exit title"]);
+    }
+
+    #[test]
+    fn pop_to_stops_at_the_matching_stage() {
+        let mut log = Log::new();
+        let mut stack = stack_of(&["title", "game", "paused"], &mut log);
+        stack.apply(
+            NextStage::PopTo(Box::new(|stage: &dyn Stage<Log>| stage.name() == "title")),
+            &mut log,
+        );
+        assert_eq!(stack.names(), vec!["title"]);
+        assert_eq!(log, vec!["exit paused", "enter title"]);
+
+        log.clear();
+        stack.apply(
+            NextStage::PopTo(Box::new(|stage: &dyn Stage<Log>| stage.name() == "missing")),
+            &mut log,
+        );
+        assert_eq!(stack.names(), vec!["title"]);
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn clear_exits_only_the_active_stages() {
+        let mut log = Log::new();
+        let mut stack = stack_of(&["title", "game"], &mut log);
+        stack.push(overlay("hud"), &mut log);
+        log.clear();
+        stack.apply(NextStage::Clear(stage("title")), &mut log);
+        assert_eq!(stack.names(), vec!["title"]);
+        assert_eq!(log, vec!["exit hud", "exit game", "enter title"]);
+    }
+
+    #[test]
+    fn transition_from_below_pops_the_stages_above_first() {
+        let mut log = Log::new();
+        let mut stack = StageStack::with_capacity(2);
+        stack.push(asking("game", NextStage::Push(stage("over"))), &mut log);
+        stack.push(overlay("hud"), &mut log);
+        log.clear();
+        stack.update(&Input::default(), &mut log);
+        assert_eq!(stack.names(), vec!["game", "over"]);
+        assert_eq!(
+            log,
+            vec![
+                "update hud",
+                "update game",
+                "exit hud",
+                "exit game",
+                "enter over"
+            ]
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use glam::Vec2;
+use snake::ai::Bot;
+
+use crate::{
+    components::{Camera2D, Input},
+    graphics,
+    stages::{
+        menu::{self, Menu},
+        GameOptions, GameState, ModeSelect, NextStage, SettingsMenu, Stage,
+    },
+};
+
+const TITLE: &str = "Snek";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum TitleEntry {
+    Play,
+    TwoPlayers,
+    VsComputer,
+    Settings,
+    Quit,
+}
+
+/// The bottom of the stage stack. Every game is pushed from here and quitting
+/// a game comes back here.
+pub struct Title {
+    menu: Menu<TitleEntry>,
+    camera: Camera2D,
+    options: GameOptions,
+}
+
+impl Title {
+    pub const NAME: &'static str = "Title";
+
+    pub fn new(input: &Input, options: GameOptions) -> Self {
+        let menu = Menu::new(vec![
+            (TitleEntry::Play, "Play".to_string()),
+            (TitleEntry::TwoPlayers, "2 Players".to_string()),
+            (TitleEntry::VsComputer, "Vs Computer".to_string()),
+            (TitleEntry::Settings, "Settings".to_string()),
+            (TitleEntry::Quit, "Quit".to_string()),
+        ]);
+        Self {
+            menu,
+            camera: Camera2D::new(input, menu::CAMERA_SCALE),
+            options,
+        }
+    }
+
+    /// Options for the next game, as given on the command line. A replay
+    /// given there only plays the first time.
+    fn game_options(&mut self) -> GameOptions {
+        let options = self.options.clone();
+        self.options.replay = None;
+        options
+    }
+
+    /// Options for a game on this machine alone, whatever the command line
+    /// said.
+    fn local_options(&mut self, players: u8, bots: Vec<Option<Bot>>) -> GameOptions {
+        let mut options = self.game_options();
+        options.players = players;
+        options.bots = bots;
+        options.net = None;
+        options
+    }
+}
+
+impl Stage for Title {
+    fn name(&self) -> &'static str {
+        Title::NAME
+    }
+
+    fn enter(&mut self, renderer: &mut graphics::MainRenderer) {
+        menu::load_assets(renderer);
+        menu::load_text(renderer, TITLE);
+        self.menu.load(renderer);
+    }
+
+    fn update(&mut self, input: &Input, renderer: &mut graphics::MainRenderer) -> NextStage {
+        // The window may have been resized while a game was on top, when this
+        // stage wasn't being updated to see it.
+        self.camera.resize(input.width, input.height);
+        match self.menu.update(input) {
+            // A replay brings its own mode along.
+            Some(TitleEntry::Play) if self.options.replay.is_some() => {
+                let game = GameState::new(input, renderer, self.game_options());
+                NextStage::Push(Box::new(game))
+            }
+            Some(TitleEntry::Play) => {
+                let modes = ModeSelect::new(input, self.game_options());
+                NextStage::Push(Box::new(modes))
+            }
+            Some(TitleEntry::TwoPlayers) => {
+                let modes = ModeSelect::new(input, self.local_options(2, Vec::new()));
+                NextStage::Push(Box::new(modes))
+            }
+            Some(TitleEntry::VsComputer) => {
+                let bots = vec![None, Some(Bot::AStar)];
+                let modes = ModeSelect::new(input, self.local_options(2, bots));
+                NextStage::Push(Box::new(modes))
+            }
+            Some(TitleEntry::Settings) => {
+                NextStage::Push(Box::new(SettingsMenu::new(self.options.settings.clone())))
+            }
+            Some(TitleEntry::Quit) => NextStage::Pop,
+            None => NextStage::Noop,
+        }
+    }
+
+    fn draw(&mut self, renderer: &mut graphics::MainRenderer) {
+        renderer.update_view(&self.camera);
+        menu::draw_text(renderer, TITLE, Vec2::new(-4., 9.));
+        self.menu.draw(renderer, Vec2::new(-4., 5.));
+    }
+}
@@ -1,317 +1,101 @@
-// TODO(jhurstwright): Replace with no_std hashmap
-use std::collections::HashMap;
-
 use glam::Vec2;
-use quad_rand as qrand;
 
 use crate::components;
-use crate::events;
-use crate::events::Event;
 use crate::graphics;
 use crate::graphics::renderer;
-
-pub struct GameWorld {
-    pub world: hecs::World,
-    pub events: Vec<events::Event>,
-    pub camera: components::Camera2D,
-}
-
-pub fn create_snake_system(game_world: &mut GameWorld) {
-    let GameWorld { world, .. } = game_world;
-    let ahead = world.spawn((
-        components::Snake,
-        components::Position(Vec2::new(0., 0.)),
-        components::Velocity(Vec2::new(0., 1.)),
-        components::HeadDirection::default(),
-        components::Material("Snake".into()),
-        components::Mesh("Snake".into()),
-    ));
-    let tail = components::Tail { segment: 1, ahead };
-
-    world.spawn((
-        tail,
-        components::Material("Tail".into()),
-        components::Mesh("Tail".into()),
-        components::Collision::snake(),
-        components::Position(Vec2::new(0., -1.)),
-    ));
-}
-
-pub fn update_input(game_world: &mut GameWorld, input: &components::Input) {
-    let GameWorld { world, .. } = game_world;
-    for (_, (vel, dir, _)) in &mut world.query::<(
-        &components::Velocity,
-        &mut components::HeadDirection,
-        &components::Snake,
-    )>() {
-        if let Some(d) = input.direction() {
-            if d.velocity() != vel.0 * -1. {
-                dir.0 = d;
-            }
-        }
-    }
-}
-
-pub fn add_food_system(game_world: &mut GameWorld) {
-    let GameWorld { world, .. } = game_world;
-    let snake_count = world.query::<&components::Food>().iter().count();
-    if snake_count >= 10 {
-        return;
-    }
-
-    let x = qrand::gen_range(-24, 24);
-    let y = qrand::gen_range(-15, 15);
-    let pos = components::Position(Vec2::new(x as f32, y as f32));
-    world.spawn((
-        pos,
-        components::Collision::food(),
-        components::Food,
-        components::Material("Food".into()),
-        components::Mesh("Food".into()),
-    ));
-}
-
-pub fn update_velocity_direction(game_world: &mut GameWorld) {
-    let GameWorld { world, .. } = game_world;
-    for (_, (velocity, direction)) in
-        &mut world.query::<(&mut components::Velocity, &components::HeadDirection)>()
-    {
-        velocity.0 = direction.0.velocity();
-    }
-}
-
-pub fn movement_system(game_world: &mut GameWorld) {
-    let GameWorld { world, .. } = game_world;
-    for (_, (pos, velocity)) in
-        &mut world.query::<(&mut components::Position, &components::Velocity)>()
-    {
-        pos.0 = pos.0 + velocity.0;
+use snake::systems::GameWorld;
+
+/// The score of a lone player, or every player's score in turn, followed by
+/// the seconds left in modes that have a time limit.
+pub fn score_text(scores: &[i32], time_left: Option<u32>) -> String {
+    let text = match scores {
+        [score] => format!("Score:  {}", score),
+        _ => scores
+            .iter()
+            .enumerate()
+            .map(|(player, score)| format!("P{}: {}", player + 1, score))
+            .collect::<Vec<_>>()
+            .join("  "),
+    };
+    match time_left {
+        Some(time_left) => format!("{}  Time: {}", text, time_left),
+        None => text,
     }
 }
 
-pub fn tail_movement_system(game_world: &mut GameWorld) {
-    let GameWorld { world, .. } = game_world;
-    let foo: HashMap<hecs::Entity, glam::Vec2> = world
-        .query::<&components::Tail>()
-        .iter()
-        .map(|(_, tail)| {
-            let pos = {
-                world
-                    .get::<components::Position>(tail.ahead)
-                    .expect("All Ahead should has Positions")
-                    .0
-            };
-            (tail.ahead.clone(), pos)
-        })
-        .collect();
-    for (_, (tail, position)) in
-        &mut world.query::<(&components::Tail, &mut components::Position)>()
-    {
-        let new_pos = foo[&tail.ahead];
-        position.0 = new_pos;
-    }
+/// Whole seconds left before the mode's time limit, rounded up.
+pub fn time_left(game_world: &GameWorld) -> Option<u32> {
+    game_world
+        .rules
+        .time_limit
+        .map(|limit| (limit - game_world.elapsed).max(0.).ceil() as u32)
 }
 
-pub fn update_score_system(
+pub fn update_score_text(
     game_world: &mut GameWorld,
-    score: &mut i32,
+    scores: &[i32],
     cmds: &mut Vec<renderer::RenderAssetCommands>,
 ) {
-    let GameWorld { world, events, .. } = game_world;
-    for event in events {
-        match event {
-            Event::SnakeEatFood { .. } => {
-                *score += 1;
-                for (_, (text, _score)) in
-                    &mut world.query::<(&mut components::Text, &components::Score)>()
-                {
-                    let cmd = text.update_text(format!("Score:  {}", score));
-                    cmds.push(cmd);
-                }
-            }
-            Event::GameOver => {
-                *score = 0;
-                for (_, (text, _score)) in
-                    &mut world.query::<(&mut components::Text, &components::Score)>()
-                {
-                    let cmd = text.update_text(format!("Score:  {}", score));
-                    cmds.push(cmd);
-                }
-            }
-            _ => {}
-        }
-    }
-}
-
-pub fn despawn_food_system(game_world: &mut GameWorld) {
-    let GameWorld { world, events, .. } = game_world;
-    for event in events {
-        match event {
-            Event::SnakeEatFood { entity, pos: _pos } => {
-                world
-                    .despawn(*entity)
-                    .expect("Food Eating System should not be destroying a non-existant Entity");
-            }
-            _ => {}
-        }
-    }
-}
-
-pub fn trigger_tail_spawn(game_world: &mut GameWorld) {
-    let GameWorld { world, events, .. } = game_world;
-    let mut events_to_push: Vec<Event> = Vec::new();
-    for event in events.iter() {
-        match event {
-            Event::SnakeEatFood { .. } => {
-                if let Some((ahead, (tail, pos))) = &world
-                    .query::<(&components::Tail, &components::Position)>()
-                    .iter()
-                    .max_by_key(|(_, (tail, _))| tail.segment)
-                {
-                    events_to_push.push(Event::SpawnSnakeTail {
-                        ahead: ahead.clone(),
-                        pos: pos.0,
-                        segment: tail.segment + 1,
-                    })
-                }
-            }
-            _ => {}
-        }
+    let time_left = time_left(game_world);
+    let GameWorld { world, .. } = game_world;
+    for (_, (text, _score)) in &mut world.query::<(&mut components::Text, &components::Score)>() {
+        let cmd = text.update_text(score_text(scores, time_left));
+        cmds.push(cmd);
     }
-    events_to_push
-        .iter()
-        .for_each(|evt| events.push(evt.clone()));
 }
 
-pub fn spawn_tail_system(game_world: &mut GameWorld) {
-    let GameWorld { world, events, .. } = game_world;
-    for event in events.iter() {
-        match event {
-            Event::SpawnSnakeTail {
-                ahead,
-                pos,
-                segment,
-            } => {
-                let tail = components::Tail {
-                    segment: segment.clone(),
-                    ahead: ahead.clone(),
-                };
-
-                world.spawn((
-                    tail,
-                    components::Material("Tail".into()),
-                    components::Mesh("Tail".into()),
-                    components::Collision::snake(),
-                    components::Position(pos.clone()),
-                ));
-            }
-            _ => {}
-        }
-    }
-}
+/// How many times a second food blinks when it is about to go.
+const BLINK_RATE: f64 = 4.;
 
-pub fn head_collision_system(game_world: &mut GameWorld) {
-    let GameWorld { world, events, .. } = game_world;
-    let (source_ent, source_pos): (hecs::Entity, Vec2) = match world
-        .query::<(
-            &components::Snake,
-            &components::Position,
-            &components::Velocity,
-        )>()
-        .iter()
-        .map(|(ent, (_, pos, vel))| (ent, pos.0 + vel.0))
-        .nth(0)
-    {
-        Some(it) => it,
-        _ => return,
-    };
-    world
-        .query::<(&components::Position, &components::Collision)>()
-        .iter()
-        .filter_map(|(ent, (target_pos, col))| {
-            if target_pos.0 == source_pos {
-                Some(Event::Collision {
-                    target: ent,
-                    source: source_ent,
-                    pos: target_pos.0,
-                    kind: col.kind,
-                })
-            } else {
-                None
-            }
-        })
-        .for_each(|event| events.push(event));
+/// Whether something about to run out of lifetime is in the off half of a
+/// blink.
+fn blinked_out(lifetime: &components::Lifetime, warning: f64) -> bool {
+    lifetime.expiring(warning) && (lifetime.remaining() * BLINK_RATE).fract() < 0.5
 }
 
-pub fn handle_collision_system(game_world: &mut GameWorld) {
-    let (collsions, rest): (Vec<Event>, Vec<Event>) =
-        game_world
-            .events
-            .iter()
-            .cloned()
-            .partition(|event| match event {
-                Event::Collision { .. } => true,
-                _ => false,
-            });
-    game_world.events = rest;
-    collsions.iter().for_each(|collision| match collision {
-        Event::Collision {
-            kind: components::CollsionKind::Snake,
-            ..
-        } => game_world.events.push(Event::GameOver),
-        Event::Collision {
-            kind: components::CollsionKind::Food,
-            target,
-            ..
-        } => {
-            let entity = target.clone();
-            let pos = game_world
-                .world
-                .get::<components::Position>(entity)
-                .expect("Food should have components::Position");
-            game_world
-                .events
-                .push(Event::SnakeEatFood { entity, pos: pos.0 });
-        }
-        _ => {}
-    });
-}
-
-pub fn game_over_system(game_world: &mut GameWorld) -> bool {
-    let GameWorld { world, events, .. } = game_world;
-
-    let filter = events
-        .iter()
-        .filter(|event| match event {
-            Event::GameOver => true,
-            _ => false,
-        })
-        .nth(0);
-    if let Some(_) = filter {
-        world.clear();
-        create_snake_system(game_world);
-        return true;
-    }
-    false
-}
-
-pub fn gather_render_cmds(game_world: &mut GameWorld, renderer: &mut graphics::MainRenderer) {
-    let GameWorld { world, .. } = game_world;
+/// Draws every mesh. Entities that move are drawn `alpha` of the way from
+/// the cell they were on before the last move tick to the one they are on now.
+/// Food that is about to run out blinks.
+pub fn gather_render_cmds(
+    game_world: &mut GameWorld,
+    renderer: &mut graphics::MainRenderer,
+    alpha: f32,
+) {
+    let GameWorld { world, rules, .. } = game_world;
     let main_draw_commands = &mut renderer.main_render_target.commands;
-    for (_, (mesh, material, pos)) in &mut world.query::<(
+    for (_, (mesh, material, pos, previous, lifetime)) in &mut world.query::<(
         &components::Mesh,
         &components::Material,
         &components::Position,
+        Option<&components::PreviousGridPos>,
+        Option<&components::Lifetime>,
     )>() {
+        if lifetime.map_or(false, |lifetime| blinked_out(lifetime, rules.food_warning)) {
+            continue;
+        }
+        let position = match previous {
+            Some(previous) => interpolate(previous.0.to_vec2(), pos.0, alpha),
+            None => pos.0,
+        };
         main_draw_commands.push(renderer::RenderCommand::DrawMesh2D(renderer::DrawMesh2D {
             rotation: 0f32,
             material: material.0.clone(),
             mesh: mesh.0.clone(),
-            position: pos.0,
+            position,
         }));
     }
 }
 
+/// Anything that moved further than a cell wrapped around the arena, and
+/// gliding it back across the whole board would look wrong, so it snaps.
+fn interpolate(previous: Vec2, current: Vec2, alpha: f32) -> Vec2 {
+    let delta = current - previous;
+    if delta.length() > 1. {
+        return current;
+    }
+    previous + delta * alpha
+}
+
 pub fn debug_render_cmds(game_world: &mut GameWorld, renderer: &mut graphics::MainRenderer) {
     let GameWorld { world, .. } = game_world;
 
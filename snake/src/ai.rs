@@ -0,0 +1,545 @@
+//! Things that can steer a snake. A controller looks at the `GameWorld`
+//! without changing it and says which way a snake should go next, so a
+//! player at the keyboard and the bots below are interchangeable.
+
+// TODO(jhurstwright): Replace with no_std hashmap
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+use crate::arena::{Arena, WallMode};
+use crate::components::{self, Direction, GridPos, Input, Player};
+use crate::systems::{self, GameWorld};
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Right,
+    Direction::Down,
+    Direction::Left,
+];
+
+/// Decides which way a snake goes.
+pub trait SnakeController {
+    /// The turn `player`'s snake should make, or `None` to carry on as it
+    /// is. Asked once per simulation tick, whether or not the snake moves on
+    /// that tick.
+    fn direction(&mut self, game_world: &GameWorld, player: Player) -> Option<Direction>;
+}
+
+/// A player at the keyboard, or whatever else filled in the `Input`.
+impl SnakeController for Input {
+    fn direction(&mut self, _: &GameWorld, player: Player) -> Option<Direction> {
+        self.player_direction(player.0)
+    }
+}
+
+/// The bots that come with the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bot {
+    Greedy,
+    AStar,
+    Hamiltonian,
+}
+
+impl Bot {
+    pub const ALL: [Bot; 3] = [Bot::Greedy, Bot::AStar, Bot::Hamiltonian];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Bot::Greedy => "greedy",
+            Bot::AStar => "astar",
+            Bot::Hamiltonian => "hamiltonian",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Bot> {
+        Bot::ALL.iter().copied().find(|bot| bot.name() == name)
+    }
+
+    pub fn controller(&self) -> Box<dyn SnakeController> {
+        match self {
+            Bot::Greedy => Box::new(GreedyBot::default()),
+            Bot::AStar => Box::new(AStarBot::default()),
+            Bot::Hamiltonian => Box::new(HamiltonianBot::default()),
+        }
+    }
+}
+
+/// Where `player`'s head is and which way it is heading, while it is alive
+/// and has no turns queued. A bot has nothing to decide otherwise.
+fn idle_head(game_world: &GameWorld, player: Player) -> Option<(GridPos, Direction)> {
+    game_world
+        .world
+        .query::<(
+            &components::Snake,
+            &Player,
+            &GridPos,
+            &components::HeadDirection,
+            &components::TurnQueue,
+        )>()
+        .iter()
+        .find(|(_, (_, owner, _, _, _))| **owner == player)
+        .filter(|(_, (_, _, _, _, turns))| turns.is_empty())
+        .map(|(_, (_, _, pos, heading, _))| (*pos, heading.0))
+}
+
+/// Remembers the cell a bot last decided on, and in which run, so it thinks
+/// once per move instead of on every tick in between.
+#[derive(Debug, Default, Clone)]
+struct LastDecision(Option<(u64, GridPos)>);
+
+impl LastDecision {
+    /// The head to plan from, unless it was already planned from this run.
+    fn head(&mut self, game_world: &GameWorld, player: Player) -> Option<(GridPos, Direction)> {
+        let (head, heading) = idle_head(game_world, player)?;
+        let decision = (game_world.run, head);
+        if self.0 == Some(decision) {
+            return None;
+        }
+        self.0 = Some(decision);
+        Some((head, heading))
+    }
+}
+
+/// A bot's picture of the arena around one snake.
+#[derive(Debug, Clone)]
+struct Board {
+    arena: Arena,
+    head: GridPos,
+    heading: Direction,
+    /// The snake's own cells, head first.
+    body: Vec<GridPos>,
+    /// Moves until each occupied cell can be entered. Tails move out of the
+    /// way as their snakes go, walls and dead snakes never do.
+    clear_at: HashMap<GridPos, usize>,
+    food: Vec<GridPos>,
+}
+
+impl Board {
+    fn new(game_world: &GameWorld, player: Player, head: GridPos, heading: Direction) -> Self {
+        let world = &game_world.world;
+        let mut lengths: HashMap<Player, usize> = HashMap::new();
+        for (_, (owner, _)) in world.query::<(&Player, &GridPos)>().iter() {
+            *lengths.entry(*owner).or_insert(0) += 1;
+        }
+        let mut clear_at = HashMap::new();
+        let mut body = Vec::new();
+        let mut food = Vec::new();
+        for (_, (pos, owner, tail, dead, is_food)) in world
+            .query::<(
+                &GridPos,
+                Option<&Player>,
+                Option<&components::Tail>,
+                Option<&components::Dead>,
+                Option<&components::Food>,
+            )>()
+            .iter()
+        {
+            match is_food {
+                // Bots steer round food that would hurt them as if it
+                // were a wall.
+                Some(components::Food(kind)) if kind.spec().harmful() => {
+                    clear_at.insert(*pos, usize::MAX);
+                    continue;
+                }
+                Some(_) => {
+                    food.push(*pos);
+                    continue;
+                }
+                None => {}
+            }
+            let owner = match (owner, dead) {
+                (Some(owner), None) => *owner,
+                _ => {
+                    clear_at.insert(*pos, usize::MAX);
+                    continue;
+                }
+            };
+            let segment = tail.map(|tail| tail.segment).unwrap_or(0);
+            // The last segment leaves its cell on the same tick a head can
+            // move into it.
+            clear_at.insert(*pos, lengths[&owner].saturating_sub(segment));
+            if owner == player {
+                body.push((segment, *pos));
+            }
+        }
+        body.sort_by_key(|(segment, _)| *segment);
+        food.sort_by_key(|pos| (pos.0, pos.1));
+        Self {
+            arena: game_world.play_arena(),
+            head,
+            heading,
+            body: body.into_iter().map(|(_, pos)| pos).collect(),
+            clear_at,
+            food,
+        }
+    }
+
+    /// Whether `pos` can be entered on the `moves`th move from now.
+    fn free(&self, pos: GridPos, moves: usize) -> bool {
+        self.arena.contains(pos) && self.clear_at.get(&pos).is_none_or(|at| *at <= moves)
+    }
+
+    /// The cells next to `pos`, in the order of `DIRECTIONS`.
+    fn neighbours(&self, pos: GridPos) -> impl Iterator<Item = (Direction, GridPos)> + '_ {
+        DIRECTIONS.iter().map(move |direction| {
+            (
+                *direction,
+                systems::next_position(&self.arena, pos, *direction),
+            )
+        })
+    }
+
+    /// Moves the head can make next, straight on first.
+    fn moves(&self) -> Vec<(Direction, GridPos)> {
+        let mut moves: Vec<(Direction, GridPos)> = self
+            .neighbours(self.head)
+            .filter(|(direction, _)| *direction != self.heading.opposite())
+            .collect();
+        moves.sort_by_key(|(direction, _)| *direction != self.heading);
+        moves
+    }
+
+    /// Cells between `a` and `b`, going round the edges if they wrap.
+    fn distance(&self, a: GridPos, b: GridPos) -> usize {
+        let (dx, dy) = ((a.0 - b.0).abs(), (a.1 - b.1).abs());
+        let (dx, dy) = match self.arena.walls {
+            WallMode::Wrap => (
+                dx.min(self.arena.width - dx),
+                dy.min(self.arena.height - dy),
+            ),
+            WallMode::Solid => (dx, dy),
+        };
+        (dx + dy) as usize
+    }
+
+    fn nearest(&self, pos: GridPos, goals: &[GridPos]) -> usize {
+        goals
+            .iter()
+            .map(|goal| self.distance(pos, *goal))
+            .min()
+            .unwrap_or(usize::MAX)
+    }
+
+    /// The shortest way from the head to any of `goals` that never runs into
+    /// anything, taking into account the tails moving out of the way. The
+    /// head isn't part of the path.
+    fn find_path(&self, goals: &[GridPos]) -> Option<Vec<GridPos>> {
+        if goals.is_empty() {
+            return None;
+        }
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<GridPos, GridPos> = HashMap::new();
+        let mut best: HashMap<GridPos, usize> = HashMap::new();
+        best.insert(self.head, 0);
+        let h = self.nearest(self.head, goals);
+        open.push(Reverse((h, 0, self.head.0, self.head.1)));
+        while let Some(Reverse((_, moves, x, y))) = open.pop() {
+            let pos = GridPos(x, y);
+            if best.get(&pos).is_some_and(|b| *b < moves) {
+                continue;
+            }
+            if moves > 0 && goals.contains(&pos) {
+                let mut path = vec![pos];
+                let mut at = pos;
+                while let Some(previous) = came_from.get(&at) {
+                    if *previous == self.head {
+                        break;
+                    }
+                    path.push(*previous);
+                    at = *previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for (direction, next) in self.neighbours(pos) {
+                if pos == self.head && direction == self.heading.opposite() {
+                    continue;
+                }
+                let next_moves = moves + 1;
+                if !self.free(next, next_moves) {
+                    continue;
+                }
+                if best.get(&next).is_some_and(|b| *b <= next_moves) {
+                    continue;
+                }
+                best.insert(next, next_moves);
+                came_from.insert(next, pos);
+                let f = next_moves + self.nearest(next, goals);
+                open.push(Reverse((f, next_moves, next.0, next.1)));
+            }
+        }
+        None
+    }
+
+    /// How the board looks once the snake has followed `path` and eaten at
+    /// the end of it.
+    fn after(&self, path: &[GridPos]) -> Board {
+        let moves = path.len();
+        let mut body: Vec<GridPos> = path.iter().rev().copied().collect();
+        body.extend(self.body.iter().copied());
+        body.truncate(self.body.len() + 1);
+
+        let mut clear_at: HashMap<GridPos, usize> = self
+            .clear_at
+            .iter()
+            .filter(|(pos, _)| !self.body.contains(pos))
+            .map(|(pos, at)| match *at {
+                usize::MAX => (*pos, usize::MAX),
+                at => (*pos, at.saturating_sub(moves)),
+            })
+            .collect();
+        for (segment, pos) in body.iter().enumerate() {
+            clear_at.insert(*pos, body.len() - segment);
+        }
+        let heading = match path {
+            [.., from, to] => self.direction_to(*from, *to),
+            [to] => self.direction_to(self.head, *to),
+            [] => None,
+        }
+        .unwrap_or(self.heading);
+        Board {
+            arena: self.arena,
+            head: body[0],
+            heading,
+            body,
+            clear_at,
+            food: Vec::new(),
+        }
+    }
+
+    fn direction_to(&self, from: GridPos, to: GridPos) -> Option<Direction> {
+        self.neighbours(from)
+            .find(|(_, pos)| *pos == to)
+            .map(|(direction, _)| direction)
+    }
+
+    /// Whether the snake can still reach its own tail, the one cell that is
+    /// sure to keep opening up in front of it.
+    fn can_reach_tail(&self) -> bool {
+        match self.body.last() {
+            Some(tail) if self.body.len() > 1 => self.find_path(&[*tail]).is_some(),
+            _ => true,
+        }
+    }
+
+    /// How many cells the snake could get to from `pos`, `moves` moves from
+    /// now.
+    fn room(&self, pos: GridPos, moves: usize) -> usize {
+        let mut seen = HashSet::new();
+        seen.insert(pos);
+        let mut queue = VecDeque::new();
+        queue.push_back((pos, moves));
+        while let Some((at, moves)) = queue.pop_front() {
+            for (_, next) in self.neighbours(at) {
+                if seen.contains(&next) || !self.free(next, moves + 1) {
+                    continue;
+                }
+                seen.insert(next);
+                queue.push_back((next, moves + 1));
+            }
+        }
+        seen.len()
+    }
+
+    /// The move that leaves the snake the most room, for when there is no
+    /// better plan.
+    fn roomiest(&self) -> Option<Direction> {
+        self.moves()
+            .into_iter()
+            .filter(|(_, next)| self.free(*next, 1))
+            .max_by_key(|(_, next)| self.room(*next, 1))
+            .map(|(direction, _)| direction)
+    }
+}
+
+/// Turns towards the closest food, as long as the next cell is free. Quick
+/// but happy to coil itself into a corner.
+#[derive(Debug, Default, Clone)]
+pub struct GreedyBot {
+    last: LastDecision,
+}
+
+impl SnakeController for GreedyBot {
+    fn direction(&mut self, game_world: &GameWorld, player: Player) -> Option<Direction> {
+        let (head, heading) = self.last.head(game_world, player)?;
+        let board = Board::new(game_world, player, head, heading);
+        board
+            .moves()
+            .into_iter()
+            .filter(|(_, next)| board.free(*next, 1))
+            .min_by_key(|(_, next)| board.nearest(*next, &board.food))
+            .map(|(direction, _)| direction)
+    }
+}
+
+/// Takes the shortest path to food, but only if it could still reach its
+/// own tail after eating. Otherwise it follows its tail, or failing that
+/// heads wherever there is the most room.
+#[derive(Debug, Default, Clone)]
+pub struct AStarBot {
+    last: LastDecision,
+}
+
+impl AStarBot {
+    fn plan(board: &Board) -> Option<Direction> {
+        let safe_meal = board
+            .find_path(&board.food)
+            .filter(|path| board.after(path).can_reach_tail());
+        let path = safe_meal.or_else(|| match board.body.last() {
+            Some(tail) if board.body.len() > 1 => board.find_path(&[*tail]),
+            _ => None,
+        });
+        match path {
+            Some(path) => board.direction_to(board.head, path[0]),
+            None => board.roomiest(),
+        }
+    }
+}
+
+impl SnakeController for AStarBot {
+    fn direction(&mut self, game_world: &GameWorld, player: Player) -> Option<Direction> {
+        let (head, heading) = self.last.head(game_world, player)?;
+        AStarBot::plan(&Board::new(game_world, player, head, heading))
+    }
+}
+
+/// Visits every cell in an order that loops back on itself, so it can never
+/// trap itself and fills the board given long enough. Arenas with an odd
+/// number of cells have no such loop, and neither does a snake that isn't
+/// on it yet, so those moves are left to `AStarBot`.
+#[derive(Debug, Default, Clone)]
+pub struct HamiltonianBot {
+    last: LastDecision,
+    /// The arena the cycle was built for and the cell after each cell on it.
+    cycle: Option<(Arena, HashMap<GridPos, GridPos>)>,
+}
+
+/// A loop through every cell of `arena`. Snakes up and down the columns
+/// above the bottom row, then comes back along it. That needs an even number
+/// of columns, or of rows, in which case the loop is turned on its side.
+pub fn hamiltonian_cycle(arena: &Arena) -> Option<Vec<GridPos>> {
+    let (min_x, min_y) = arena.min();
+    let transpose = arena.width % 2 != 0;
+    let (columns, rows) = if transpose {
+        (arena.height, arena.width)
+    } else {
+        (arena.width, arena.height)
+    };
+    if columns % 2 != 0 || rows < 2 {
+        return None;
+    }
+    let cell = |column: i32, row: i32| {
+        if transpose {
+            GridPos(min_x + row, min_y + column)
+        } else {
+            GridPos(min_x + column, min_y + row)
+        }
+    };
+    let mut cycle = Vec::with_capacity((columns * rows) as usize);
+    for column in 0..columns {
+        if column % 2 == 0 {
+            cycle.extend((1..rows).map(|row| cell(column, row)));
+        } else {
+            cycle.extend((1..rows).rev().map(|row| cell(column, row)));
+        }
+    }
+    cycle.extend((0..columns).rev().map(|column| cell(column, 0)));
+    Some(cycle)
+}
+
+impl HamiltonianBot {
+    fn successor(&mut self, arena: &Arena, pos: GridPos) -> Option<GridPos> {
+        let stale = match &self.cycle {
+            Some((built_for, _)) => built_for != arena,
+            None => true,
+        };
+        if stale {
+            let next = hamiltonian_cycle(arena)
+                .map(|cycle| {
+                    cycle
+                        .iter()
+                        .zip(cycle.iter().cycle().skip(1))
+                        .map(|(from, to)| (*from, *to))
+                        .collect()
+                })
+                .unwrap_or_default();
+            self.cycle = Some((*arena, next));
+        }
+        self.cycle
+            .as_ref()
+            .and_then(|(_, next)| next.get(&pos).copied())
+    }
+}
+
+impl SnakeController for HamiltonianBot {
+    fn direction(&mut self, game_world: &GameWorld, player: Player) -> Option<Direction> {
+        let (head, heading) = self.last.head(game_world, player)?;
+        let board = Board::new(game_world, player, head, heading);
+        let on_cycle = self
+            .successor(&game_world.play_arena(), head)
+            .filter(|next| board.free(*next, 1))
+            .and_then(|next| board.direction_to(head, next))
+            .filter(|direction| *direction != heading.opposite());
+        on_cycle.or_else(|| AStarBot::plan(&board))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arena::WallMode;
+    use crate::batch::{self, BatchConfig};
+    use crate::events::Event;
+    use crate::systems::{self, Outcome};
+
+    /// A snake of two in a 10 by 10 walled arena, its head at `head` and
+    /// about to run into the wall.
+    fn cornered(head: GridPos, heading: Direction) -> GameWorld {
+        let mut game_world = GameWorld::new(0);
+        game_world.arena = Arena::new(10, 10, WallMode::Solid);
+        systems::create_arena_system(&mut game_world);
+        let GameWorld { world, grid, .. } = &mut game_world;
+        let player = Player(0);
+        let ahead = systems::spawn_snake_head(world, grid, player, head, heading);
+        let tail = components::Tail { segment: 1, ahead };
+        let behind = head.offset(heading.opposite());
+        systems::spawn_snake_tail(world, grid, player, behind, tail);
+        game_world
+    }
+
+    #[test]
+    fn bots_turn_away_from_walls() {
+        let corners = [
+            (GridPos(-5, 4), Direction::Up),
+            (GridPos(4, 4), Direction::Right),
+            (GridPos(4, -5), Direction::Down),
+            (GridPos(-5, -5), Direction::Left),
+        ];
+        for bot in Bot::ALL.iter() {
+            for (head, heading) in corners.iter() {
+                let mut game_world = cornered(*head, *heading);
+                let mut controller = bot.controller();
+                systems::control_system(&mut game_world, Player(0), controller.as_mut());
+                let move_time = game_world.rules.move_time;
+                systems::step_system(&mut game_world, move_time);
+                let died = game_world
+                    .events
+                    .iter()
+                    .any(|event| matches!(event, Event::SnakeDied { .. }));
+                assert!(!died, "{} ran into the wall at {:?}", bot.name(), head);
+            }
+        }
+    }
+
+    #[test]
+    fn hamiltonian_fills_a_small_arena() {
+        let config = BatchConfig {
+            bot: Bot::Hamiltonian,
+            arena: Arena::new(6, 6, WallMode::Solid),
+            ..Default::default()
+        };
+        let report = batch::run_game(&config, 1);
+        assert_eq!(report.outcome, Some(Outcome::Won));
+        assert_eq!(report.snakes[0].length, 36);
+    }
+}
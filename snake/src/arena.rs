@@ -0,0 +1,104 @@
+use crate::components::GridPos;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WallMode {
+    /// The arena is fenced in by walls, running into one ends the game.
+    Solid,
+    /// Leaving one side of the arena brings the snake back on the other.
+    Wrap,
+}
+
+impl WallMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WallMode::Solid => "solid",
+            WallMode::Wrap => "wrap",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<WallMode> {
+        match name {
+            "solid" => Some(WallMode::Solid),
+            "wrap" => Some(WallMode::Wrap),
+            _ => None,
+        }
+    }
+}
+
+/// The playable area, in cells, centered on the origin. Food placement,
+/// movement, walls and the camera all work from this one definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arena {
+    pub width: i32,
+    pub height: i32,
+    pub walls: WallMode,
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Self {
+            width: 48,
+            height: 30,
+            walls: WallMode::Solid,
+        }
+    }
+}
+
+impl Arena {
+    pub fn new(width: i32, height: i32, walls: WallMode) -> Self {
+        Self {
+            width,
+            height,
+            walls,
+        }
+    }
+
+    /// Lowest cell inside the arena.
+    pub fn min(&self) -> (i32, i32) {
+        (-self.width / 2, -self.height / 2)
+    }
+
+    /// One past the highest cell inside the arena.
+    pub fn max(&self) -> (i32, i32) {
+        let (x, y) = self.min();
+        (x + self.width, y + self.height)
+    }
+
+    pub fn contains(&self, pos: GridPos) -> bool {
+        let (min_x, min_y) = self.min();
+        let (max_x, max_y) = self.max();
+        pos.0 >= min_x && pos.0 < max_x && pos.1 >= min_y && pos.1 < max_y
+    }
+
+    /// Every cell inside the arena.
+    pub fn cells(&self) -> impl Iterator<Item = GridPos> {
+        let (min_x, min_y) = self.min();
+        let (max_x, max_y) = self.max();
+        (min_x..max_x).flat_map(move |x| (min_y..max_y).map(move |y| GridPos(x, y)))
+    }
+
+    /// The ring of cells just outside the arena.
+    pub fn wall_cells(&self) -> Vec<GridPos> {
+        let (min_x, min_y) = self.min();
+        let (max_x, max_y) = self.max();
+        let mut cells = Vec::with_capacity(2 * (self.width + self.height) as usize + 4);
+        for x in (min_x - 1)..=max_x {
+            cells.push(GridPos(x, min_y - 1));
+            cells.push(GridPos(x, max_y));
+        }
+        for y in min_y..max_y {
+            cells.push(GridPos(min_x - 1, y));
+            cells.push(GridPos(max_x, y));
+        }
+        cells
+    }
+
+    /// Brings a cell that left the arena back in on the other side.
+    pub fn wrap(&self, pos: GridPos) -> GridPos {
+        let (min_x, min_y) = self.min();
+        GridPos(
+            (pos.0 - min_x).rem_euclid(self.width) + min_x,
+            (pos.1 - min_y).rem_euclid(self.height) + min_y,
+        )
+    }
+}
@@ -0,0 +1,253 @@
+//! Plays games back to back with bots at the controls and no window, so
+//! changes to the rules can be judged over thousands of runs instead of by
+//! feel.
+
+use crate::ai::{Bot, SnakeController};
+use crate::arena::Arena;
+use crate::clock;
+use crate::components::{self, Action, Player};
+use crate::events::{DeathCause, Event};
+use crate::level::Level;
+use crate::mode::{GameMode, Rules};
+use crate::rng::Rng;
+use crate::systems::{self, GameWorld, Outcome};
+
+#[derive(Debug, Clone)]
+pub struct BatchConfig {
+    pub games: usize,
+    /// Seeds the seed of every game, so a batch can be run again exactly.
+    pub seed: u64,
+    /// Drives every snake.
+    pub bot: Bot,
+    /// From 1 to `Action::MAX_PLAYERS`, anything outside is clamped.
+    pub players: u8,
+    pub arena: Arena,
+    /// Played on in place of `arena` when set.
+    pub level: Option<Level>,
+    /// Length of a simulation tick, in seconds.
+    pub step: f64,
+    pub mode: GameMode,
+    /// Starts out as the rules of `mode` and can be tuned from there.
+    pub rules: Rules,
+    /// Games still going after this many simulated seconds are called off.
+    pub time_limit: f64,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            games: 1000,
+            seed: 0,
+            bot: Bot::AStar,
+            players: 1,
+            arena: Arena::default(),
+            level: None,
+            step: clock::STEP,
+            mode: GameMode::Classic,
+            rules: GameMode::Classic.rules(),
+            time_limit: 600.,
+        }
+    }
+}
+
+/// How one snake did in one game.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnakeReport {
+    pub score: i32,
+    pub length: usize,
+    /// `None` if the snake was still going when the game ended.
+    pub death: Option<DeathCause>,
+}
+
+/// How one game went.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameReport {
+    pub seed: u64,
+    /// `None` if the game hit the time limit.
+    pub outcome: Option<Outcome>,
+    /// Simulated seconds the game lasted.
+    pub time: f64,
+    /// One entry per snake, in player order.
+    pub snakes: Vec<SnakeReport>,
+    /// Seconds the board spent with no food on it.
+    pub food_wait: f64,
+    /// How many separate times the board ran out of food.
+    pub food_waits: usize,
+    /// Pieces of food that ran out of lifetime uneaten.
+    pub food_expired: usize,
+}
+
+/// Plays a single game from `seed` until it ends or runs out of time.
+pub fn run_game(config: &BatchConfig, seed: u64) -> GameReport {
+    let mut game_world = GameWorld::new(seed);
+    game_world.arena = config.arena;
+    if let Some(level) = &config.level {
+        game_world.set_level(level.clone());
+    }
+    game_world.players = config.players.clamp(1, Action::MAX_PLAYERS);
+    game_world.mode = config.mode;
+    game_world.rules = config.rules;
+    game_world.apply_rules();
+    systems::create_arena_system(&mut game_world);
+    systems::create_snake_system(&mut game_world);
+
+    let players = game_world.players as usize;
+    let mut controllers: Vec<Box<dyn SnakeController>> =
+        (0..players).map(|_| config.bot.controller()).collect();
+    let mut scores = vec![0; players];
+    let mut deaths = vec![None; players];
+    let mut food_wait = 0.;
+    let mut food_waits = 0;
+    let mut food_expired = 0;
+    let mut had_food = true;
+    let mut outcome = None;
+    while outcome.is_none() && game_world.elapsed < config.time_limit {
+        for (player, controller) in controllers.iter_mut().enumerate() {
+            let player = Player(player as u8);
+            systems::control_system(&mut game_world, player, controller.as_mut());
+        }
+        systems::step_system(&mut game_world, config.step);
+        systems::despawn_food_system(&mut game_world);
+        systems::update_score_system(&mut game_world, &mut scores);
+        systems::speed_system(&mut game_world, &scores);
+        for event in game_world.events.iter() {
+            match event {
+                Event::SnakeDied { player, cause, .. } => {
+                    deaths[player.0 as usize] = Some(*cause);
+                }
+                Event::FoodExpired { .. } => food_expired += 1,
+                _ => {}
+            }
+        }
+        outcome = systems::game_over_system(&game_world);
+
+        let has_food = game_world
+            .world
+            .query::<&components::Food>()
+            .iter()
+            .next()
+            .is_some();
+        if !has_food {
+            food_wait += config.step;
+            if had_food {
+                food_waits += 1;
+            }
+        }
+        had_food = has_food;
+        game_world.events.clear();
+    }
+
+    let snakes = (0..players)
+        .map(|player| SnakeReport {
+            score: scores[player],
+            length: systems::snake_length(&game_world, Player(player as u8)),
+            death: deaths[player],
+        })
+        .collect();
+    GameReport {
+        seed,
+        outcome,
+        time: game_world.elapsed,
+        snakes,
+        food_wait,
+        food_waits,
+        food_expired,
+    }
+}
+
+/// Plays `config.games` games, each seeded from the one before.
+pub fn run_batch(config: &BatchConfig) -> Vec<GameReport> {
+    let mut rng = Rng::new(config.seed);
+    (0..config.games)
+        .map(|_| run_game(config, rng.next_u64()))
+        .collect()
+}
+
+/// What a batch of games adds up to. Scores and lengths are per snake, so
+/// a two player game counts twice towards them.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Summary {
+    pub games: usize,
+    pub lost: usize,
+    pub won: usize,
+    pub winner: usize,
+    pub draw: usize,
+    /// Games the mode's own time limit ended, as opposed to `timeout`.
+    pub time_up: usize,
+    pub timeout: usize,
+    pub mean_score: f64,
+    pub min_score: i32,
+    pub max_score: i32,
+    pub mean_length: f64,
+    /// Mean seconds a game lasted.
+    pub mean_time: f64,
+    pub max_time: f64,
+    pub wall_deaths: usize,
+    pub self_deaths: usize,
+    pub other_deaths: usize,
+    pub poison_deaths: usize,
+    /// Mean seconds the board stayed empty each time it ran out of food.
+    pub mean_food_wait: f64,
+    /// Share of all simulated time the board had no food on it.
+    pub starved: f64,
+    /// Mean pieces of food that ran out of lifetime uneaten per game.
+    pub mean_food_expired: f64,
+}
+
+fn mean(total: f64, count: usize) -> f64 {
+    if count == 0 {
+        0.
+    } else {
+        total / count as f64
+    }
+}
+
+impl Summary {
+    pub fn new(reports: &[GameReport]) -> Self {
+        let mut summary = Summary {
+            games: reports.len(),
+            ..Default::default()
+        };
+        let snakes: Vec<&SnakeReport> = reports.iter().flat_map(|report| &report.snakes).collect();
+        summary.min_score = snakes.iter().map(|snake| snake.score).min().unwrap_or(0);
+        summary.max_score = snakes.iter().map(|snake| snake.score).max().unwrap_or(0);
+        let scores: i32 = snakes.iter().map(|snake| snake.score).sum();
+        let lengths: usize = snakes.iter().map(|snake| snake.length).sum();
+        summary.mean_score = mean(scores as f64, snakes.len());
+        summary.mean_length = mean(lengths as f64, snakes.len());
+        for snake in snakes.iter() {
+            match snake.death {
+                Some(DeathCause::Wall) => summary.wall_deaths += 1,
+                Some(DeathCause::Own) => summary.self_deaths += 1,
+                Some(DeathCause::Other) => summary.other_deaths += 1,
+                Some(DeathCause::Poison) => summary.poison_deaths += 1,
+                None => {}
+            }
+        }
+
+        let mut time = 0.;
+        let mut food_wait = 0.;
+        let mut food_waits = 0;
+        let mut food_expired = 0;
+        for report in reports {
+            match report.outcome {
+                Some(Outcome::Lost) => summary.lost += 1,
+                Some(Outcome::Won) => summary.won += 1,
+                Some(Outcome::Winner(_)) => summary.winner += 1,
+                Some(Outcome::Draw) => summary.draw += 1,
+                Some(Outcome::TimeUp) => summary.time_up += 1,
+                None => summary.timeout += 1,
+            }
+            time += report.time;
+            summary.max_time = summary.max_time.max(report.time);
+            food_wait += report.food_wait;
+            food_waits += report.food_waits;
+            food_expired += report.food_expired;
+        }
+        summary.mean_time = mean(time, reports.len());
+        summary.mean_food_wait = mean(food_wait, food_waits);
+        summary.mean_food_expired = mean(food_expired as f64, reports.len());
+        summary.starved = if time > 0. { food_wait / time } else { 0. };
+        summary
+    }
+}
@@ -0,0 +1,216 @@
+//! Plays a networked game between two bots with no window, to try lockstep
+//! out on one machine. Start one process per player:
+//!
+//! ```text
+//! netplay --local 127.0.0.1:7000 --peer 127.0.0.1:7001 --player 0 &
+//! netplay --local 127.0.0.1:7001 --peer 127.0.0.1:7000 --player 1
+//! ```
+//!
+//! `--loss <share>`, `--latency <seconds>` and `--jitter <seconds>` make the
+//! connection worse on purpose, `--delay <ticks>` sets the input delay,
+//! `--ticks <n>` how long to play for, `--seed <n>` the host's seed and
+//! `--bot <name>` who plays. Runs end with a new one started, as after a
+//! retry. Exits with 3 if the worlds drifted apart, 4 if the peer went
+//! quiet for `--timeout <seconds>` and 5 if it plays with other settings.
+//! Both sides play classic on the default arena.
+
+use std::net::SocketAddr;
+use std::process;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use snake::ai::{Bot, SnakeController};
+use snake::arena::Arena;
+use snake::clock::STEP;
+use snake::components::{Input, Player};
+use snake::mode::GameMode;
+use snake::net::{self, Lockstep, NetConfig};
+use snake::systems::{self, GameWorld};
+
+struct Options {
+    config: NetConfig,
+    seed: u64,
+    bot: Bot,
+    ticks: u32,
+    timeout: f64,
+}
+
+fn value<T, I>(args: &mut I, flag: &str) -> Result<T, String>
+where
+    T: FromStr,
+    I: Iterator<Item = String>,
+{
+    let arg = args
+        .next()
+        .ok_or_else(|| format!("{} needs a value", flag))?;
+    arg.parse()
+        .map_err(|_| format!("{} can't be {:?}", flag, arg))
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut local: Option<SocketAddr> = None;
+    let mut peer: Option<SocketAddr> = None;
+    let mut player = 0;
+    let mut config = NetConfig::new(
+        SocketAddr::from(([127, 0, 0, 1], 0)),
+        SocketAddr::from(([127, 0, 0, 1], 0)),
+        0,
+    );
+    let mut options = Options {
+        config: config.clone(),
+        seed: 1,
+        bot: Bot::AStar,
+        ticks: 60 * 60,
+        timeout: 10.,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--local" => local = Some(value(&mut args, &arg)?),
+            "--peer" => peer = Some(value(&mut args, &arg)?),
+            "--player" => player = value(&mut args, &arg)?,
+            "--delay" => config.delay = value(&mut args, &arg)?,
+            "--loss" => config.loss = value(&mut args, &arg)?,
+            "--latency" => config.latency = value(&mut args, &arg)?,
+            "--jitter" => config.jitter = value(&mut args, &arg)?,
+            "--seed" => options.seed = value(&mut args, &arg)?,
+            "--ticks" => options.ticks = value(&mut args, &arg)?,
+            "--timeout" => options.timeout = value(&mut args, &arg)?,
+            "--bot" => {
+                let name: String = value(&mut args, &arg)?;
+                options.bot =
+                    Bot::from_name(&name).ok_or_else(|| format!("unknown bot {:?}", name))?;
+            }
+            _ => return Err(format!("unknown option {:?}", arg)),
+        }
+    }
+    if player > 1 {
+        return Err("--player has to be 0 or 1".to_string());
+    }
+    config.local = local.ok_or("--local is needed")?;
+    config.peer = peer.ok_or("--peer is needed")?;
+    config.player = player;
+    options.config = config;
+    Ok(options)
+}
+
+fn new_world(seed: u64) -> GameWorld {
+    let mut game_world = GameWorld::new(seed);
+    game_world.players = 2;
+    systems::create_arena_system(&mut game_world);
+    systems::create_snake_system(&mut game_world);
+    game_world
+}
+
+/// The bot's move this tick, pressed the way a local player would.
+fn bot_input(bot: &mut dyn SnakeController, game_world: &GameWorld, lockstep: &Lockstep) -> Input {
+    let mut input = Input::default();
+    if let Some(direction) = bot.direction(game_world, Player(lockstep.player())) {
+        input.press_move(0, direction);
+    }
+    input
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let mut lockstep = options
+        .config
+        .open(
+            options.seed,
+            net::settings_hash(GameMode::Classic, &Arena::default(), 2, None),
+        )
+        .map_err(|err| format!("can't open {}: {}", options.config.local, err))?;
+    let mut bot = options.bot.controller();
+    let mut game_world: Option<GameWorld> = None;
+    let mut scores = vec![0; 2];
+    let mut runs = 0;
+    let mut heard = Instant::now();
+    let mut last_tick = 0;
+
+    while lockstep.tick() < options.ticks {
+        lockstep.poll().map_err(|err| err.to_string())?;
+        if lockstep.mismatch() {
+            eprintln!("peer plays with other settings");
+            process::exit(5);
+        }
+        if game_world.is_none() {
+            if let Some(seed) = lockstep.seed().filter(|_| lockstep.connected()) {
+                game_world = Some(new_world(seed));
+            }
+        }
+        if let Some(game_world) = &mut game_world {
+            let input = bot_input(bot.as_mut(), game_world, &lockstep);
+            lockstep.push_local(&input);
+            while let Some(input) = lockstep.next_input() {
+                systems::update_input(game_world, &input);
+                systems::step_system(game_world, STEP);
+                systems::despawn_food_system(game_world);
+                systems::update_score_system(game_world, &mut scores);
+                systems::speed_system(game_world, &scores);
+                if systems::game_over_system(game_world).is_some() {
+                    runs += 1;
+                    let seed = game_world.rng.next_u64();
+                    game_world.reseed(seed);
+                    systems::reset_system(game_world);
+                    scores.iter_mut().for_each(|score| *score = 0);
+                }
+                game_world.events.clear();
+                lockstep.record_hash(net::state_hash(game_world));
+                if let Some(desync) = lockstep.desync() {
+                    eprintln!(
+                        "desync at tick {}: {:016x} here, {:016x} there",
+                        desync.tick, desync.local, desync.remote
+                    );
+                    process::exit(3);
+                }
+                if lockstep.tick() >= options.ticks {
+                    break;
+                }
+                let input = bot_input(bot.as_mut(), game_world, &lockstep);
+                lockstep.push_local(&input);
+            }
+        }
+        lockstep.send().map_err(|err| err.to_string())?;
+
+        if lockstep.tick() != last_tick {
+            last_tick = lockstep.tick();
+            heard = Instant::now();
+        } else if heard.elapsed().as_secs_f64() > options.timeout {
+            eprintln!("peer went quiet at tick {}", lockstep.tick());
+            process::exit(4);
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    // Keep answering for a moment so the peer gets the last of our input.
+    let linger = Instant::now();
+    while linger.elapsed() < Duration::from_secs(1) {
+        lockstep.poll().map_err(|err| err.to_string())?;
+        lockstep.send().map_err(|err| err.to_string())?;
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    let hash = game_world.as_ref().map(net::state_hash).unwrap_or(0);
+    println!(
+        "player {} reached tick {} after {} runs, hash {:016x}",
+        lockstep.player(),
+        lockstep.tick(),
+        runs,
+        hash
+    );
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("netplay: {}", err);
+            process::exit(2);
+        }
+    };
+    if let Err(err) = run(options) {
+        eprintln!("netplay: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,626 @@
+//! Runs a batch of games with bots and prints what they added up to.
+//!
+//! ```text
+//! simulate [--games <n>] [--seed <n>] [--bot greedy|astar|hamiltonian]
+//!          [--players <n>] [--size <width>x<height>] [--wrap]
+//!          [--level <path>]
+//!          [--mode classic|time_attack|survival|zen]
+//!          [--move-time <seconds>] [--food-time <seconds>] [--max-food <n>]
+//!          [--food-lifetime <seconds>|forever]
+//!          [--speed <curve>] [--speed-basis length|score]
+//!          [--min-move-time <seconds>] [--time-limit <seconds>]
+//!          [--format json|csv] [--per-game] [--output <path>]
+//! ```
+//!
+//! `--move-time`, the food flags and the speed flags tune the
+//! rules of the mode, wherever they come on the command line. A curve is
+//! `constant`, `linear:<step>`, `stepped:<every>:<step>` or
+//! `exponential:<factor>`. A level brings its own arena, so `--size` and
+//! `--wrap` do nothing with one.
+//!
+//! The summary comes out as a JSON object or as `metric,value` rows. With
+//! `--per-game` the JSON gains a `games` array and the CSV becomes one row
+//! per snake per game instead.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process;
+use std::str::FromStr;
+
+use snake::ai::Bot;
+use snake::arena::WallMode;
+use snake::batch::{self, BatchConfig, GameReport, Summary};
+use snake::components::Action;
+use snake::level::Level;
+use snake::mode::{GameMode, SpeedBasis, SpeedCurve};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Csv,
+}
+
+struct Options {
+    config: BatchConfig,
+    format: Format,
+    per_game: bool,
+    output: Option<String>,
+}
+
+fn value<T, I>(args: &mut I, flag: &str) -> Result<T, String>
+where
+    T: FromStr,
+    I: Iterator<Item = String>,
+{
+    let arg = args
+        .next()
+        .ok_or_else(|| format!("{} needs a value", flag))?;
+    arg.parse()
+        .map_err(|_| format!("{} can't be {:?}", flag, arg))
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        config: BatchConfig::default(),
+        format: Format::Json,
+        per_game: false,
+        output: None,
+    };
+    let config = &mut options.config;
+    let mut move_time = None;
+    let mut food_time = None;
+    let mut max_food = None;
+    let mut food_lifetime = None;
+    let mut speed = None;
+    let mut speed_basis = None;
+    let mut min_move_time = None;
+    let mut level = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--games" => config.games = value(&mut args, &arg)?,
+            "--seed" => config.seed = value(&mut args, &arg)?,
+            "--bot" => {
+                let name: String = value(&mut args, &arg)?;
+                config.bot =
+                    Bot::from_name(&name).ok_or_else(|| format!("unknown bot {:?}", name))?;
+            }
+            "--players" => {
+                config.players = value(&mut args, &arg)?;
+                if !(1..=Action::MAX_PLAYERS).contains(&config.players) {
+                    return Err(format!("--players takes 1 to {}", Action::MAX_PLAYERS));
+                }
+            }
+            "--size" => {
+                let size: String = value(&mut args, &arg)?;
+                let mut parts = size.split('x').map(str::parse::<i32>);
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some(Ok(width)), Some(Ok(height)), None) if width > 0 && height > 0 => {
+                        config.arena.width = width;
+                        config.arena.height = height;
+                    }
+                    _ => return Err(format!("--size can't be {:?}", size)),
+                }
+            }
+            "--wrap" => config.arena.walls = WallMode::Wrap,
+            "--level" => level = Some(value::<String, _>(&mut args, &arg)?),
+            "--mode" => {
+                let name: String = value(&mut args, &arg)?;
+                config.mode =
+                    GameMode::from_name(&name).ok_or_else(|| format!("unknown mode {:?}", name))?;
+            }
+            "--move-time" => move_time = Some(value(&mut args, &arg)?),
+            "--food-time" => food_time = Some(value(&mut args, &arg)?),
+            "--max-food" => max_food = Some(value(&mut args, &arg)?),
+            "--food-lifetime" => {
+                let lifetime: String = value(&mut args, &arg)?;
+                food_lifetime = match lifetime.as_str() {
+                    "forever" => Some(None),
+                    _ => Some(Some(lifetime.parse().map_err(|_| {
+                        format!("--food-lifetime can't be {:?}", lifetime)
+                    })?)),
+                };
+            }
+            "--speed" => {
+                let curve: String = value(&mut args, &arg)?;
+                speed = Some(
+                    SpeedCurve::parse(&curve)
+                        .ok_or_else(|| format!("unknown speed curve {:?}", curve))?,
+                );
+            }
+            "--speed-basis" => {
+                let name: String = value(&mut args, &arg)?;
+                speed_basis = Some(
+                    SpeedBasis::from_name(&name)
+                        .ok_or_else(|| format!("unknown speed basis {:?}", name))?,
+                );
+            }
+            "--min-move-time" => min_move_time = Some(value(&mut args, &arg)?),
+            "--time-limit" => config.time_limit = value(&mut args, &arg)?,
+            "--format" => {
+                let format: String = value(&mut args, &arg)?;
+                options.format = match format.as_str() {
+                    "json" => Format::Json,
+                    "csv" => Format::Csv,
+                    _ => return Err(format!("unknown format {:?}", format)),
+                };
+            }
+            "--per-game" => options.per_game = true,
+            "--output" => options.output = Some(value(&mut args, &arg)?),
+            _ => return Err(format!("unknown option {:?}", arg)),
+        }
+    }
+    if let Some(path) = level {
+        let level = Level::load(&path).map_err(|err| format!("can't load {}: {}", path, err))?;
+        level
+            .validate(config.players)
+            .map_err(|err| format!("can't play {}: {}", path, err))?;
+        config.arena = level.arena;
+        config.level = Some(level);
+    }
+    config.rules = config.mode.rules();
+    let rules = &mut config.rules;
+    rules.move_time = move_time.unwrap_or(rules.move_time);
+    rules.food_time = food_time.unwrap_or(rules.food_time);
+    rules.max_food = max_food.unwrap_or(rules.max_food);
+    rules.food_lifetime = food_lifetime.unwrap_or(rules.food_lifetime);
+    rules.speed = speed.unwrap_or(rules.speed);
+    rules.speed_basis = speed_basis.unwrap_or(rules.speed_basis);
+    rules.min_move_time = min_move_time.unwrap_or(rules.min_move_time);
+    Ok(options)
+}
+
+fn death_name(report: &batch::SnakeReport) -> &'static str {
+    report.death.map(|cause| cause.name()).unwrap_or("")
+}
+
+fn outcome_name(report: &GameReport) -> &'static str {
+    report
+        .outcome
+        .map(|outcome| outcome.name())
+        .unwrap_or("timeout")
+}
+
+/// `text` as a JSON string, quotes and all.
+fn json_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn write_json(
+    out: &mut impl Write,
+    config: &BatchConfig,
+    summary: &Summary,
+    reports: Option<&[GameReport]>,
+) -> io::Result<()> {
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"config\": {{")?;
+    writeln!(out, "    \"games\": {},", config.games)?;
+    writeln!(out, "    \"seed\": {},", config.seed)?;
+    writeln!(out, "    \"bot\": \"{}\",", config.bot.name())?;
+    writeln!(out, "    \"players\": {},", config.players)?;
+    // What the games were played on, a mode that doesn't kill wraps around.
+    let arena = config.rules.play_arena(config.arena);
+    writeln!(out, "    \"width\": {},", arena.width)?;
+    writeln!(out, "    \"height\": {},", arena.height)?;
+    writeln!(out, "    \"walls\": \"{}\",", arena.walls.name())?;
+    match &config.level {
+        Some(level) => writeln!(out, "    \"level\": {},", json_string(&level.name))?,
+        None => writeln!(out, "    \"level\": null,")?,
+    }
+    writeln!(out, "    \"mode\": \"{}\",", config.mode.name())?;
+    writeln!(out, "    \"deadly\": {},", config.rules.deadly)?;
+    writeln!(out, "    \"move_time\": {},", config.rules.move_time)?;
+    writeln!(out, "    \"speed\": \"{}\",", config.rules.speed)?;
+    writeln!(
+        out,
+        "    \"speed_basis\": \"{}\",",
+        config.rules.speed_basis.name()
+    )?;
+    writeln!(
+        out,
+        "    \"min_move_time\": {},",
+        config.rules.min_move_time
+    )?;
+    writeln!(out, "    \"food_time\": {},", config.rules.food_time)?;
+    writeln!(out, "    \"max_food\": {},", config.rules.max_food)?;
+    match config.rules.food_lifetime {
+        Some(lifetime) => writeln!(out, "    \"food_lifetime\": {},", lifetime)?,
+        None => writeln!(out, "    \"food_lifetime\": null,")?,
+    }
+    writeln!(out, "    \"food_points\": {},", config.rules.food_points)?;
+    writeln!(out, "    \"varied_food\": {},", config.rules.varied_food)?;
+    match config.rules.time_limit {
+        Some(limit) => writeln!(out, "    \"mode_time_limit\": {},", limit)?,
+        None => writeln!(out, "    \"mode_time_limit\": null,")?,
+    }
+    writeln!(out, "    \"time_limit\": {}", config.time_limit)?;
+    writeln!(out, "  }},")?;
+    writeln!(out, "  \"summary\": {{")?;
+    let rows = summary_rows(summary);
+    for (index, (name, value)) in rows.iter().enumerate() {
+        let comma = if index + 1 < rows.len() { "," } else { "" };
+        writeln!(out, "    \"{}\": {}{}", name, value, comma)?;
+    }
+    match reports {
+        Some(reports) => {
+            writeln!(out, "  }},")?;
+            writeln!(out, "  \"games\": [")?;
+            for (index, report) in reports.iter().enumerate() {
+                let snakes: Vec<String> = report
+                    .snakes
+                    .iter()
+                    .map(|snake| {
+                        let death = match snake.death {
+                            Some(cause) => format!("\"{}\"", cause.name()),
+                            None => "null".to_string(),
+                        };
+                        format!(
+                            "{{\"score\": {}, \"length\": {}, \"death\": {}}}",
+                            snake.score, snake.length, death
+                        )
+                    })
+                    .collect();
+                let comma = if index + 1 < reports.len() { "," } else { "" };
+                writeln!(
+                    out,
+                    "    {{\"seed\": {}, \"outcome\": \"{}\", \"time\": {}, \"food_wait\": {}, \"food_expired\": {}, \"snakes\": [{}]}}{}",
+                    report.seed,
+                    outcome_name(report),
+                    report.time,
+                    report.food_wait,
+                    report.food_expired,
+                    snakes.join(", "),
+                    comma
+                )?;
+            }
+            writeln!(out, "  ]")?;
+        }
+        None => writeln!(out, "  }}")?,
+    }
+    writeln!(out, "}}")
+}
+
+fn summary_rows(summary: &Summary) -> Vec<(&'static str, String)> {
+    vec![
+        ("games", summary.games.to_string()),
+        ("lost", summary.lost.to_string()),
+        ("won", summary.won.to_string()),
+        ("winner", summary.winner.to_string()),
+        ("draw", summary.draw.to_string()),
+        ("time_up", summary.time_up.to_string()),
+        ("timeout", summary.timeout.to_string()),
+        ("mean_score", summary.mean_score.to_string()),
+        ("min_score", summary.min_score.to_string()),
+        ("max_score", summary.max_score.to_string()),
+        ("mean_length", summary.mean_length.to_string()),
+        ("mean_time", summary.mean_time.to_string()),
+        ("max_time", summary.max_time.to_string()),
+        ("wall_deaths", summary.wall_deaths.to_string()),
+        ("self_deaths", summary.self_deaths.to_string()),
+        ("other_deaths", summary.other_deaths.to_string()),
+        ("poison_deaths", summary.poison_deaths.to_string()),
+        ("mean_food_wait", summary.mean_food_wait.to_string()),
+        ("starved", summary.starved.to_string()),
+        ("mean_food_expired", summary.mean_food_expired.to_string()),
+    ]
+}
+
+fn write_csv(
+    out: &mut impl Write,
+    summary: &Summary,
+    reports: Option<&[GameReport]>,
+) -> io::Result<()> {
+    let reports = match reports {
+        Some(reports) => reports,
+        None => {
+            writeln!(out, "metric,value")?;
+            for (name, value) in summary_rows(summary) {
+                writeln!(out, "{},{}", name, value)?;
+            }
+            return Ok(());
+        }
+    };
+    writeln!(
+        out,
+        "game,seed,outcome,time,food_wait,food_expired,player,score,length,death"
+    )?;
+    for (game, report) in reports.iter().enumerate() {
+        for (player, snake) in report.snakes.iter().enumerate() {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{},{}",
+                game,
+                report.seed,
+                outcome_name(report),
+                report.time,
+                report.food_wait,
+                report.food_expired,
+                player,
+                snake.score,
+                snake.length,
+                death_name(snake)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("simulate: {}", err);
+            process::exit(2);
+        }
+    };
+    let reports = batch::run_batch(&options.config);
+    let summary = Summary::new(&reports);
+    let reports = if options.per_game {
+        Some(reports.as_slice())
+    } else {
+        None
+    };
+
+    let mut out: Box<dyn Write> = match &options.output {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(err) => {
+                eprintln!("simulate: can't create {}: {}", path, err);
+                process::exit(1);
+            }
+        },
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let written = match options.format {
+        Format::Json => write_json(&mut out, &options.config, &summary, reports),
+        Format::Csv => write_csv(&mut out, &summary, reports),
+    }
+    .and_then(|_| out.flush());
+    if let Err(err) = written {
+        eprintln!("simulate: {}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::iter::Peekable;
+    use std::str::Chars;
+
+    use snake::arena::Arena;
+
+    /// Just enough JSON to read back what `write_json` writes.
+    #[derive(Debug, Clone, PartialEq)]
+    enum Json {
+        Null,
+        Bool(bool),
+        Number(f64),
+        String(String),
+        Array(Vec<Json>),
+        Object(Vec<(String, Json)>),
+    }
+
+    impl Json {
+        fn get(&self, key: &str) -> &Json {
+            match self {
+                Json::Object(fields) => fields
+                    .iter()
+                    .find(|(name, _)| name == key)
+                    .map(|(_, value)| value)
+                    .unwrap_or_else(|| panic!("no {:?} in {:?}", key, self)),
+                _ => panic!("{:?} isn't an object", self),
+            }
+        }
+    }
+
+    fn skip_space(chars: &mut Peekable<Chars>) {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+    }
+
+    fn expect(chars: &mut Peekable<Chars>, word: &str) {
+        for c in word.chars() {
+            assert_eq!(chars.next(), Some(c));
+        }
+    }
+
+    fn parse_string(chars: &mut Peekable<Chars>) -> String {
+        expect(chars, "\"");
+        let mut text = String::new();
+        loop {
+            match chars.next().expect("unterminated string") {
+                '"' => return text,
+                '\\' => match chars.next().expect("unterminated escape") {
+                    'n' => text.push('\n'),
+                    'r' => text.push('\r'),
+                    't' => text.push('\t'),
+                    'u' => {
+                        let hex: String = chars.by_ref().take(4).collect();
+                        let code = u32::from_str_radix(&hex, 16).unwrap();
+                        text.push(char::from_u32(code).unwrap());
+                    }
+                    c @ ('"' | '\\' | '/') => text.push(c),
+                    c => panic!("unknown escape \\{}", c),
+                },
+                c => {
+                    assert!(c as u32 >= 0x20, "raw control character in a string");
+                    text.push(c);
+                }
+            }
+        }
+    }
+
+    fn parse_value(chars: &mut Peekable<Chars>) -> Json {
+        skip_space(chars);
+        let value = match *chars.peek().expect("value") {
+            '{' => {
+                chars.next();
+                let mut fields = Vec::new();
+                loop {
+                    skip_space(chars);
+                    if chars.peek() == Some(&'}') {
+                        chars.next();
+                        break;
+                    }
+                    let key = parse_string(chars);
+                    skip_space(chars);
+                    expect(chars, ":");
+                    fields.push((key, parse_value(chars)));
+                    skip_space(chars);
+                    if chars.peek() == Some(&',') {
+                        chars.next();
+                    }
+                }
+                Json::Object(fields)
+            }
+            '[' => {
+                chars.next();
+                let mut items = Vec::new();
+                loop {
+                    skip_space(chars);
+                    if chars.peek() == Some(&']') {
+                        chars.next();
+                        break;
+                    }
+                    items.push(parse_value(chars));
+                    skip_space(chars);
+                    if chars.peek() == Some(&',') {
+                        chars.next();
+                    }
+                }
+                Json::Array(items)
+            }
+            '"' => Json::String(parse_string(chars)),
+            'n' => {
+                expect(chars, "null");
+                Json::Null
+            }
+            't' => {
+                expect(chars, "true");
+                Json::Bool(true)
+            }
+            'f' => {
+                expect(chars, "false");
+                Json::Bool(false)
+            }
+            _ => {
+                let mut number = String::new();
+                while let Some(c) = chars.peek().filter(|c| "+-.eE0123456789".contains(**c)) {
+                    number.push(*c);
+                    chars.next();
+                }
+                Json::Number(
+                    number
+                        .parse()
+                        .unwrap_or_else(|_| panic!("bad number {:?}", number)),
+                )
+            }
+        };
+        skip_space(chars);
+        value
+    }
+
+    fn parse_json(text: &str) -> Json {
+        let mut chars = text.chars().peekable();
+        let value = parse_value(&mut chars);
+        assert_eq!(chars.next(), None, "trailing text after the JSON");
+        value
+    }
+
+    const LEVEL_NAME: &str = "a \"quoted\" \\ name\nover\ttwo lines\u{1}";
+
+    fn zen_batch() -> (BatchConfig, Vec<GameReport>) {
+        let config = BatchConfig {
+            games: 3,
+            level: Some(Level::open(LEVEL_NAME, Arena::default())),
+            mode: GameMode::Zen,
+            rules: GameMode::Zen.rules(),
+            time_limit: 20.,
+            ..Default::default()
+        };
+        let reports = batch::run_batch(&config);
+        (config, reports)
+    }
+
+    #[test]
+    fn json_output_parses_back() {
+        let (config, reports) = zen_batch();
+        let summary = Summary::new(&reports);
+        let mut out = Vec::new();
+        write_json(&mut out, &config, &summary, Some(&reports)).unwrap();
+        let json = parse_json(&String::from_utf8(out).unwrap());
+
+        let written = json.get("config");
+        assert_eq!(written.get("level"), &Json::String(LEVEL_NAME.to_string()));
+        // Zen doesn't kill, so its walls wrap whatever the arena says.
+        assert_eq!(written.get("walls"), &Json::String("wrap".to_string()));
+        assert_eq!(written.get("deadly"), &Json::Bool(false));
+        assert_eq!(
+            json.get("summary").get("games"),
+            &Json::Number(reports.len() as f64)
+        );
+        let games = match json.get("games") {
+            Json::Array(games) => games,
+            other => panic!("games should be an array, not {:?}", other),
+        };
+        let seeds: Vec<&Json> = games.iter().map(|game| game.get("seed")).collect();
+        let expected: Vec<Json> = reports
+            .iter()
+            .map(|report| Json::Number(report.seed as f64))
+            .collect();
+        assert_eq!(seeds, expected.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn csv_output_parses_back() {
+        let (_, reports) = zen_batch();
+        let summary = Summary::new(&reports);
+
+        let mut out = Vec::new();
+        write_csv(&mut out, &summary, None).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("metric,value"));
+        let rows: Vec<(String, String)> = lines
+            .map(|line| {
+                let (name, value) = line.split_once(',').unwrap();
+                (name.to_string(), value.to_string())
+            })
+            .collect();
+        let expected: Vec<(String, String)> = summary_rows(&summary)
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        assert_eq!(rows, expected);
+
+        let mut out = Vec::new();
+        write_csv(&mut out, &summary, Some(&reports)).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let mut lines = text.lines();
+        let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+        let rows: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+        assert_eq!(rows.len(), reports.len());
+        for (row, report) in rows.iter().zip(reports.iter()) {
+            assert_eq!(row.len(), header.len());
+            assert_eq!(row[1], report.seed.to_string());
+            assert_eq!(row[2], outcome_name(report));
+            assert_eq!(row[8], report.snakes[0].length.to_string());
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Length of a simulation tick, in seconds, in the game and the tools that
+/// play it headless.
+pub const STEP: f64 = 1. / 60.;
+
+/// Where the simulation clock reads the current time from, in seconds.
+pub trait TimeSource {
+    fn now(&self) -> f64;
+}
+
+/// Time that only moves when told to. Clones share the same time, so a test
+/// can keep one handle and give the other to a `SimClock`.
+#[derive(Debug, Default, Clone)]
+pub struct ManualTime(Rc<Cell<f64>>);
+
+impl ManualTime {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn advance(&self, seconds: f64) {
+        self.0.set(self.0.get() + seconds);
+    }
+}
+
+impl TimeSource for ManualTime {
+    fn now(&self) -> f64 {
+        self.0.get()
+    }
+}
+
+/// Fixed timestep accumulator. Real time read from the `TimeSource` is
+/// banked, and `advance` reports how many whole steps of `step` seconds the
+/// simulation should run this frame.
+pub struct SimClock {
+    source: Box<dyn TimeSource>,
+    step: f64,
+    accumulator: f64,
+    last: Option<f64>,
+    paused: bool,
+    ticks: u64,
+}
+
+impl SimClock {
+    /// Upper bound on steps run for a single frame, so a long stall (dragging
+    /// the window, a breakpoint) doesn't turn into a spiral of catch up.
+    pub const MAX_STEPS_PER_ADVANCE: usize = 8;
+
+    pub fn new<T: TimeSource + 'static>(step: f64, source: T) -> Self {
+        Self {
+            source: Box::new(source),
+            step,
+            accumulator: 0.,
+            last: None,
+            paused: false,
+            ticks: 0,
+        }
+    }
+
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// How far we are between the last step and the next one, from 0 to 1.
+    pub fn alpha(&self) -> f64 {
+        self.accumulator / self.step
+    }
+
+    pub fn advance(&mut self) -> usize {
+        if self.paused {
+            return 0;
+        }
+        let now = self.source.now();
+        let last = self.last.replace(now).unwrap_or(now);
+        self.accumulator += (now - last).max(0.);
+
+        let mut steps = 0;
+        while self.accumulator >= self.step && steps < Self::MAX_STEPS_PER_ADVANCE {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        if steps == Self::MAX_STEPS_PER_ADVANCE {
+            self.accumulator %= self.step;
+        }
+        self.ticks += steps as u64;
+        steps
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Resumes without counting the time spent paused.
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.last = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{GridPos, Snake};
+    use crate::systems::{self, GameWorld};
+
+    #[test]
+    fn fixed_steps_move_the_snake_on_schedule() {
+        let time = ManualTime::new();
+        let mut clock = SimClock::new(STEP, time.clone());
+        let mut game_world = GameWorld::new(0);
+        systems::create_arena_system(&mut game_world);
+        systems::create_snake_system(&mut game_world);
+
+        // The first reading only sets where the clock counts from.
+        assert_eq!(clock.advance(), 0);
+        for _ in 0..62 {
+            // A hair over three steps, so rounding in the banked time never
+            // drops one.
+            time.advance(STEP * 3. + 1e-9);
+            for _ in 0..clock.advance() {
+                systems::step_system(&mut game_world, STEP);
+            }
+        }
+
+        assert_eq!(clock.ticks(), 186);
+        let heads: Vec<GridPos> = game_world
+            .world
+            .query::<(&Snake, &GridPos)>()
+            .iter()
+            .map(|(_, (_, pos))| *pos)
+            .collect();
+        // 3.1 seconds is twelve quarter second moves, heading up from the
+        // middle of the arena. A quarter second isn't a whole number of
+        // steps, so this only holds if moves don't lose the time they ran
+        // over by.
+        assert_eq!(heads, vec![GridPos(0, 12)]);
+    }
+}
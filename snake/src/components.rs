@@ -0,0 +1,346 @@
+use std::collections::VecDeque;
+
+use glam::Vec2;
+
+use crate::food::FoodKind;
+
+pub use crate::input::{Action, Input};
+
+// fn lerp(low: f64, high: f64, value: f64) -> f64 {
+//     ((1. - value) * low + high * value).min(high).max(low)
+// }
+
+fn inv_lerp(low: f64, high: f64, alpha: f64) -> f64 {
+    (alpha - low) / (high - low)
+}
+
+// fn remap( original_min: f32, original_max: f32, new_min: f32, new_max: f32, value: f32) -> f32 {
+//     let alpha = inv_lerp(original_min, original_max, value);
+//     lerp(new_min, new_max, alpha)
+// }
+
+/// Counts simulated seconds. Timers never look at the wall clock, they are
+/// moved forward by the fixed step of the `SimClock`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timer {
+    elapsed: f64,
+    duration: f64,
+}
+
+impl Timer {
+    pub fn new(duration: f64) -> Self {
+        Self {
+            elapsed: 0.,
+            duration,
+        }
+    }
+
+    pub fn tick(&mut self, dt: f64) {
+        self.elapsed += dt;
+    }
+
+    /// Starts the timer over, keeping whatever time ran past the end so a
+    /// timer that repeats doesn't drift behind its duration.
+    pub fn reset(&mut self) {
+        self.elapsed = (self.elapsed - self.duration).max(0.);
+    }
+
+    pub fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    pub fn alpha(&self) -> f64 {
+        inv_lerp(0., self.duration, self.elapsed).clamp(0., 1.)
+    }
+
+    /// Moves the timer to `alpha` of the way through its duration.
+    pub fn set_alpha(&mut self, alpha: f64) {
+        self.elapsed = alpha * self.duration;
+    }
+
+    /// Changes how long the timer runs for while keeping how far through it
+    /// is, so a timer halfway to finishing stays halfway.
+    pub fn set_duration(&mut self, duration: f64) {
+        let alpha = self.alpha();
+        self.duration = duration;
+        self.set_alpha(alpha);
+    }
+}
+
+pub struct Snake;
+pub struct Score;
+pub struct Food(pub FoodKind);
+pub struct Wall;
+
+/// Counts down to something disappearing, such as food left uneaten.
+pub struct Lifetime(pub Timer);
+
+impl Lifetime {
+    pub fn new(seconds: f64) -> Self {
+        Self(Timer::new(seconds))
+    }
+
+    /// Seconds until it is gone.
+    pub fn remaining(&self) -> f64 {
+        (self.0.duration() - self.0.elapsed()).max(0.)
+    }
+
+    /// Whether it is into its last `warning` seconds, when it should warn
+    /// that it is about to go.
+    pub fn expiring(&self, warning: f64) -> bool {
+        self.remaining() <= warning
+    }
+}
+
+pub struct Tail {
+    pub segment: usize,
+    pub ahead: hecs::Entity,
+}
+
+/// Which player a snake's head and every segment of its tail belong to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Player(pub u8);
+
+impl Player {
+    /// The first player keeps the plain material names, the others get
+    /// their number on the end so each can have a colour of their own.
+    pub fn head_material(&self) -> String {
+        match self.0 {
+            0 => "Snake".to_string(),
+            n => format!("Snake{}", n),
+        }
+    }
+
+    pub fn tail_material(&self) -> String {
+        match self.0 {
+            0 => "Tail".to_string(),
+            n => format!("Tail{}", n),
+        }
+    }
+}
+
+/// Marks the head and tail of a snake that crashed. It stays where it died,
+/// in the way of the snakes still going, until the run ends.
+pub struct Dead;
+
+/// The cell an entity occupies. This is what the simulation works with,
+/// `Position` only mirrors it for rendering.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GridPos(pub i32, pub i32);
+
+impl GridPos {
+    pub fn offset(&self, direction: Direction) -> GridPos {
+        let (x, y) = direction.offset();
+        GridPos(self.0 + x, self.1 + y)
+    }
+
+    pub fn to_vec2(&self) -> Vec2 {
+        Vec2::new(self.0 as f32, self.1 as f32)
+    }
+}
+
+/// Where a moving entity was before the last move tick, so the renderer can
+/// glide it between the two cells.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PreviousGridPos(pub GridPos);
+
+pub struct Position(pub Vec2);
+/// The way a snake head moves, one cell per move tick.
+pub struct Velocity(pub Direction);
+
+#[derive(Debug, Copy, Clone)]
+pub enum CollsionKind {
+    Snake,
+    Food,
+    Wall,
+}
+pub struct Collision {
+    pub kind: CollsionKind,
+}
+
+impl Collision {
+    pub fn snake() -> Self {
+        Self {
+            kind: CollsionKind::Snake,
+        }
+    }
+
+    pub fn food() -> Self {
+        Self {
+            kind: CollsionKind::Food,
+        }
+    }
+
+    pub fn wall() -> Self {
+        Self {
+            kind: CollsionKind::Wall,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    #[default]
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Right,
+        Direction::Down,
+        Direction::Left,
+    ];
+
+    pub fn velocity(&self) -> Vec2 {
+        match self {
+            Direction::Up => Vec2::new(0., 1.),
+            Direction::Right => Vec2::new(1., 0.),
+            Direction::Down => Vec2::new(0., -1.),
+            Direction::Left => Vec2::new(-1., 0.),
+        }
+    }
+
+    pub fn offset(&self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, 1),
+            Direction::Right => (1, 0),
+            Direction::Down => (0, -1),
+            Direction::Left => (-1, 0),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Right => "right",
+            Direction::Down => "down",
+            Direction::Left => "left",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Direction> {
+        match name {
+            "up" => Some(Direction::Up),
+            "right" => Some(Direction::Right),
+            "down" => Some(Direction::Down),
+            "left" => Some(Direction::Left),
+            _ => None,
+        }
+    }
+
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Right => Direction::Left,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+        }
+    }
+
+    pub fn update(&mut self, input: &Input) {
+        if input.pressed(Action::MoveLeft) {
+            if self == &Direction::Right {
+                return;
+            }
+            *self = Direction::Left;
+        }
+        if input.pressed(Action::MoveRight) {
+            if self == &Direction::Left {
+                return;
+            }
+            *self = Direction::Right;
+        }
+        if input.pressed(Action::MoveDown) {
+            if self == &Direction::Up {
+                return;
+            }
+            *self = Direction::Down;
+        }
+        if input.pressed(Action::MoveUp) {
+            if self == &Direction::Down {
+                return;
+            }
+            *self = Direction::Up;
+        }
+    }
+}
+
+/// Where the snake will go on its next move, the last queued turn or its
+/// current velocity if nothing is queued.
+#[derive(Debug, Default)]
+pub struct HeadDirection(pub Direction);
+
+/// Turns waiting to be made, one per move tick, so quick presses in between
+/// two moves aren't lost.
+#[derive(Debug, Default, Clone)]
+pub struct TurnQueue(VecDeque<Direction>);
+
+impl TurnQueue {
+    /// Turns beyond this many are dropped.
+    pub const CAPACITY: usize = 3;
+
+    /// Queues a turn unless it is a no-op or a reversal of the turn before
+    /// it, or of `velocity` when nothing is queued. Returns whether it was
+    /// queued.
+    pub fn push(&mut self, direction: Direction, velocity: Direction) -> bool {
+        let last = self.last().unwrap_or(velocity);
+        if self.0.len() >= Self::CAPACITY || direction == last || direction == last.opposite() {
+            return false;
+        }
+        self.0.push_back(direction);
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<Direction> {
+        self.0.pop_front()
+    }
+
+    pub fn last(&self) -> Option<Direction> {
+        self.0.back().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct AssetIdentity(pub String);
+
+impl From<String> for AssetIdentity {
+    fn from(v: String) -> Self {
+        Self(v)
+    }
+}
+
+impl From<&'_ str> for AssetIdentity {
+    fn from(v: &'_ str) -> Self {
+        Self(v.to_owned())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Material(pub AssetIdentity);
+
+#[derive(Debug, Clone)]
+pub struct Mesh(pub AssetIdentity);
@@ -0,0 +1,427 @@
+//! Turns touch and gamepad input into `Action`s. Nothing here knows about a
+//! window system, front ends forward their events and feed the `Input` that
+//! comes out to the stages as usual.
+
+use crate::components::Direction;
+use crate::input::{Action, Input};
+
+/// How far touches and sticks have to move before they count.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviceConfig {
+    /// Stick deflection, from 0 to 1, below which the stick is centred.
+    pub dead_zone: f32,
+    /// How far a finger has to travel to swipe, as a fraction of the
+    /// shorter side of the screen.
+    pub swipe_threshold: f32,
+    /// Taps closer to the middle of the screen than this, again as a
+    /// fraction of the shorter side, confirm instead of turning.
+    pub tap_centre: f32,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self {
+            dead_zone: 0.3,
+            swipe_threshold: 0.08,
+            tap_centre: 0.15,
+        }
+    }
+}
+
+/// The way a vector mostly points. `y` grows downwards, as it does on
+/// screens.
+fn dominant_direction(x: f32, y: f32) -> Direction {
+    if x.abs() > y.abs() {
+        if x > 0. {
+            Direction::Right
+        } else {
+            Direction::Left
+        }
+    } else if y > 0. {
+        Direction::Down
+    } else {
+        Direction::Up
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TouchPhase {
+    Started,
+    Moved,
+    Ended,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Touch {
+    id: u64,
+    /// Where the finger went down, or where its last swipe ended.
+    origin: (f32, f32),
+    /// Set once the touch has swiped or paused, so lifting it isn't a tap.
+    spent: bool,
+}
+
+/// Reads swipes and taps from touch events.
+///
+/// Dragging a finger further than the swipe threshold turns that way, and a
+/// finger can keep swiping without being lifted. A tap that never swiped
+/// acts as an on-screen d-pad, turning towards whichever edge of the screen
+/// it was nearest, or confirms if it was in the middle. Putting a second
+/// finger down pauses.
+#[derive(Debug, Clone, Default)]
+pub struct TouchTracker {
+    pub config: DeviceConfig,
+    touches: Vec<Touch>,
+}
+
+impl TouchTracker {
+    pub fn new(config: DeviceConfig) -> Self {
+        Self {
+            config,
+            touches: Vec::new(),
+        }
+    }
+
+    /// Feeds one touch event in screen pixels, pressing any actions it
+    /// completes on `input`. The screen size is taken from `input`.
+    pub fn touch(&mut self, phase: TouchPhase, id: u64, x: f32, y: f32, input: &mut Input) {
+        let scale = input.width.min(input.height);
+        match phase {
+            TouchPhase::Started => {
+                self.touches.retain(|touch| touch.id != id);
+                let paused = !self.touches.is_empty();
+                if paused {
+                    input.press(Action::Pause);
+                    for touch in self.touches.iter_mut() {
+                        touch.spent = true;
+                    }
+                }
+                self.touches.push(Touch {
+                    id,
+                    origin: (x, y),
+                    spent: paused,
+                });
+            }
+            TouchPhase::Moved => {
+                let threshold = self.config.swipe_threshold * scale;
+                let touch = match self.touches.iter_mut().find(|touch| touch.id == id) {
+                    Some(touch) => touch,
+                    None => return,
+                };
+                let (dx, dy) = (x - touch.origin.0, y - touch.origin.1);
+                if (dx * dx + dy * dy).sqrt() < threshold {
+                    return;
+                }
+                input.press(Action::from(dominant_direction(dx, dy)));
+                touch.origin = (x, y);
+                touch.spent = true;
+            }
+            TouchPhase::Ended => {
+                let index = match self.touches.iter().position(|touch| touch.id == id) {
+                    Some(index) => index,
+                    None => return,
+                };
+                let touch = self.touches.remove(index);
+                if touch.spent {
+                    return;
+                }
+                let (dx, dy) = (x - input.width / 2., y - input.height / 2.);
+                if (dx * dx + dy * dy).sqrt() < self.config.tap_centre * scale {
+                    input.press(Action::Confirm);
+                } else {
+                    input.press(Action::from(dominant_direction(dx, dy)));
+                }
+            }
+            TouchPhase::Cancelled => self.touches.retain(|touch| touch.id != id),
+        }
+    }
+}
+
+/// What a gamepad looked like when it was last polled.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GamepadState {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+    /// Left stick, each axis from -1 to 1 with `y` growing downwards.
+    pub stick: (f32, f32),
+    pub confirm: bool,
+    pub back: bool,
+    pub pause: bool,
+}
+
+/// Reads a polled gamepad. Actions are pressed when a button goes down or
+/// the d-pad or stick starts pointing somewhere new, so holding one doesn't
+/// repeat it every frame.
+#[derive(Debug, Clone, Default)]
+pub struct Gamepad {
+    pub config: DeviceConfig,
+    held: Option<Direction>,
+    previous: GamepadState,
+}
+
+impl Gamepad {
+    pub fn new(config: DeviceConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// The way the d-pad points, or the stick if the d-pad is released.
+    fn direction(&self, state: &GamepadState) -> Option<Direction> {
+        match (state.left, state.right, state.up, state.down) {
+            (true, false, _, _) => return Some(Direction::Left),
+            (false, true, _, _) => return Some(Direction::Right),
+            (_, _, true, false) => return Some(Direction::Up),
+            (_, _, false, true) => return Some(Direction::Down),
+            _ => {}
+        }
+        let (x, y) = state.stick;
+        if (x * x + y * y).sqrt() < self.config.dead_zone {
+            return None;
+        }
+        Some(dominant_direction(x, y))
+    }
+
+    pub fn update(&mut self, state: &GamepadState, input: &mut Input) {
+        let direction = self.direction(state);
+        if let Some(direction) = direction {
+            if self.held != Some(direction) {
+                input.press(Action::from(direction));
+            }
+        }
+        self.held = direction;
+
+        let buttons = [
+            (state.confirm, self.previous.confirm, Action::Confirm),
+            (state.back, self.previous.back, Action::Back),
+            (state.pause, self.previous.pause, Action::Pause),
+        ];
+        for (down, was_down, action) in buttons.iter() {
+            if *down && !*was_down {
+                input.press(*action);
+            }
+        }
+        self.previous = *state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An 800 by 600 screen, so swipes need 48 pixels and the middle is the
+    /// 90 pixels around (400, 300).
+    fn screen() -> Input {
+        Input {
+            width: 800.,
+            height: 600.,
+            ..Default::default()
+        }
+    }
+
+    fn tap(x: f32, y: f32) -> Input {
+        let mut tracker = TouchTracker::default();
+        let mut input = screen();
+        tracker.touch(TouchPhase::Started, 0, x, y, &mut input);
+        tracker.touch(TouchPhase::Ended, 0, x, y, &mut input);
+        input
+    }
+
+    #[test]
+    fn drags_below_the_threshold_do_not_swipe() {
+        let mut tracker = TouchTracker::default();
+        let mut input = screen();
+        tracker.touch(TouchPhase::Started, 0, 100., 100., &mut input);
+        tracker.touch(TouchPhase::Moved, 0, 130., 100., &mut input);
+        tracker.touch(TouchPhase::Moved, 0, 140., 120., &mut input);
+        assert!(input.actions.is_empty());
+    }
+
+    #[test]
+    fn swipes_turn_and_keep_going_without_lifting() {
+        let mut tracker = TouchTracker::default();
+        let mut input = screen();
+        tracker.touch(TouchPhase::Started, 0, 100., 100., &mut input);
+        tracker.touch(TouchPhase::Moved, 0, 160., 110., &mut input);
+        assert!(input.pressed(Action::MoveRight));
+
+        input.reset();
+        tracker.touch(TouchPhase::Moved, 0, 170., 170., &mut input);
+        assert!(input.pressed(Action::MoveDown));
+
+        // Lifting a finger that swiped isn't a tap as well.
+        input.reset();
+        tracker.touch(TouchPhase::Ended, 0, 170., 170., &mut input);
+        assert!(input.actions.is_empty());
+    }
+
+    #[test]
+    fn taps_turn_towards_the_nearest_edge() {
+        assert!(tap(400., 20.).pressed(Action::MoveUp));
+        assert!(tap(400., 580.).pressed(Action::MoveDown));
+        assert!(tap(20., 300.).pressed(Action::MoveLeft));
+        assert!(tap(780., 300.).pressed(Action::MoveRight));
+    }
+
+    #[test]
+    fn taps_in_the_middle_confirm() {
+        let input = tap(420., 290.);
+        assert!(input.pressed(Action::Confirm));
+        assert!(input.direction().is_none());
+    }
+
+    #[test]
+    fn a_second_finger_pauses_and_spends_both() {
+        let mut tracker = TouchTracker::default();
+        let mut input = screen();
+        tracker.touch(TouchPhase::Started, 0, 100., 100., &mut input);
+        tracker.touch(TouchPhase::Started, 1, 700., 500., &mut input);
+        assert!(input.pressed(Action::Pause));
+
+        input.reset();
+        tracker.touch(TouchPhase::Ended, 1, 700., 500., &mut input);
+        tracker.touch(TouchPhase::Ended, 0, 100., 100., &mut input);
+        assert!(input.actions.is_empty());
+    }
+
+    #[test]
+    fn cancelled_touches_do_nothing() {
+        let mut tracker = TouchTracker::default();
+        let mut input = screen();
+        tracker.touch(TouchPhase::Started, 0, 400., 20., &mut input);
+        tracker.touch(TouchPhase::Cancelled, 0, 400., 20., &mut input);
+        tracker.touch(TouchPhase::Ended, 0, 400., 20., &mut input);
+        assert!(input.actions.is_empty());
+    }
+
+    /// Feeds `states` to a fresh gamepad one poll at a time and collects
+    /// the input each poll pressed.
+    fn poll(config: DeviceConfig, states: &[GamepadState]) -> Vec<Input> {
+        let mut gamepad = Gamepad::new(config);
+        states
+            .iter()
+            .map(|state| {
+                let mut input = Input::default();
+                gamepad.update(state, &mut input);
+                input
+            })
+            .collect()
+    }
+
+    fn stick(x: f32, y: f32) -> GamepadState {
+        GamepadState {
+            stick: (x, y),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn the_dpad_turns_once_per_press() {
+        let left = GamepadState {
+            left: true,
+            ..Default::default()
+        };
+        let inputs = poll(
+            DeviceConfig::default(),
+            &[left, left, GamepadState::default(), left],
+        );
+        assert!(inputs[0].pressed(Action::MoveLeft));
+        assert!(!inputs[1].pressed(Action::MoveLeft));
+        assert!(!inputs[2].pressed(Action::MoveLeft));
+        assert!(inputs[3].pressed(Action::MoveLeft));
+    }
+
+    #[test]
+    fn every_dpad_direction_turns_that_way() {
+        let pressed = |state: GamepadState, action: Action| {
+            poll(DeviceConfig::default(), &[state])[0].pressed(action)
+        };
+        let up = GamepadState {
+            up: true,
+            ..Default::default()
+        };
+        let down = GamepadState {
+            down: true,
+            ..Default::default()
+        };
+        let right = GamepadState {
+            right: true,
+            ..Default::default()
+        };
+        assert!(pressed(up, Action::MoveUp));
+        assert!(pressed(down, Action::MoveDown));
+        assert!(pressed(right, Action::MoveRight));
+        // The d-pad wins over the stick.
+        let both = GamepadState {
+            stick: (0., 1.),
+            ..up
+        };
+        assert!(pressed(both, Action::MoveUp));
+        assert!(!pressed(both, Action::MoveDown));
+    }
+
+    #[test]
+    fn the_stick_is_centred_inside_the_dead_zone() {
+        let config = DeviceConfig::default();
+        let inside = poll(config, &[stick(0.29, 0.), stick(0., -0.2), stick(0.2, 0.2)]);
+        assert!(inside.iter().all(|input| !input.any_direction()));
+
+        let outside = poll(config, &[stick(0.31, 0.)]);
+        assert!(outside[0].pressed(Action::MoveRight));
+        // Mostly down, and out of the dead zone though neither axis is.
+        let diagonal = poll(config, &[stick(0.2, 0.25)]);
+        assert!(diagonal[0].pressed(Action::MoveDown));
+        let up = poll(config, &[stick(0., -0.5)]);
+        assert!(up[0].pressed(Action::MoveUp));
+    }
+
+    #[test]
+    fn the_stick_turns_again_after_it_recentres() {
+        let inputs = poll(
+            DeviceConfig::default(),
+            &[
+                stick(-0.8, 0.),
+                stick(-0.9, 0.1),
+                stick(-0.1, 0.),
+                stick(-0.8, 0.),
+            ],
+        );
+        let turned: Vec<bool> = inputs
+            .iter()
+            .map(|input| input.pressed(Action::MoveLeft))
+            .collect();
+        assert_eq!(turned, vec![true, false, false, true]);
+    }
+
+    #[test]
+    fn the_dead_zone_is_configurable() {
+        let config = DeviceConfig {
+            dead_zone: 0.6,
+            ..Default::default()
+        };
+        let inputs = poll(config, &[stick(0.5, 0.), stick(0.7, 0.)]);
+        assert!(!inputs[0].any_direction());
+        assert!(inputs[1].pressed(Action::MoveRight));
+    }
+
+    #[test]
+    fn buttons_press_when_they_go_down() {
+        let confirm = GamepadState {
+            confirm: true,
+            ..Default::default()
+        };
+        let pause = GamepadState {
+            pause: true,
+            back: true,
+            ..Default::default()
+        };
+        let inputs = poll(DeviceConfig::default(), &[confirm, confirm, pause]);
+        assert!(inputs[0].pressed(Action::Confirm));
+        assert!(!inputs[1].pressed(Action::Confirm));
+        assert!(inputs[2].pressed(Action::Pause));
+        assert!(inputs[2].pressed(Action::Back));
+    }
+}
@@ -0,0 +1,83 @@
+#![allow(dead_code)]
+use crate::components;
+use crate::food::FoodKind;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Event {
+    Noop,
+    /// `entity` is the food, `snake` the head of the snake that ate it.
+    SnakeEatFood {
+        entity: hecs::Entity,
+        pos: components::GridPos,
+        snake: hecs::Entity,
+        player: components::Player,
+        kind: FoodKind,
+    },
+    /// Food was left uneaten until its lifetime ran out, and is gone.
+    FoodExpired {
+        entity: hecs::Entity,
+        pos: components::GridPos,
+        kind: FoodKind,
+    },
+    /// `player`'s snake grows a segment.
+    Grow {
+        player: components::Player,
+    },
+    /// Moves take `factor` times as long for the next `seconds`.
+    SlowTime {
+        factor: f64,
+        seconds: f64,
+    },
+    SpawnSnakeTail {
+        ahead: hecs::Entity,
+        pos: components::GridPos,
+        segment: usize,
+        player: components::Player,
+    },
+    Collision {
+        target: hecs::Entity,
+        source: hecs::Entity,
+        pos: components::GridPos,
+        kind: components::CollsionKind,
+    },
+    /// `snake` is the head of the snake that crashed.
+    SnakeDied {
+        snake: hecs::Entity,
+        player: components::Player,
+        cause: DeathCause,
+    },
+    /// `player`'s snake loses `segment` and everything behind it, from
+    /// running into its own tail where crashing doesn't kill or from food.
+    TrimTail {
+        player: components::Player,
+        segment: usize,
+    },
+    GameOver,
+    /// Every cell is filled, so there is nowhere left to put food.
+    GameWon,
+    /// The mode's time limit ran out.
+    TimeUp,
+}
+
+/// What a snake crashed into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeathCause {
+    Wall,
+    /// Its own tail.
+    Own,
+    /// Another snake, alive or dead.
+    Other,
+    /// Something it ate.
+    Poison,
+}
+
+impl DeathCause {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeathCause::Wall => "wall",
+            DeathCause::Own => "self",
+            DeathCause::Other => "other",
+            DeathCause::Poison => "poison",
+        }
+    }
+}
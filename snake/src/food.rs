@@ -0,0 +1,164 @@
+use crate::components::Timer;
+use crate::rng::Rng;
+
+/// The kinds of food that can spawn. What each one does is in `FOOD_TABLE`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FoodKind {
+    #[default]
+    Plain,
+    Golden,
+    Shrink,
+    Slow,
+    Poison,
+}
+
+impl FoodKind {
+    pub const ALL: [FoodKind; 5] = [
+        FoodKind::Plain,
+        FoodKind::Golden,
+        FoodKind::Shrink,
+        FoodKind::Slow,
+        FoodKind::Poison,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FoodKind::Plain => "plain",
+            FoodKind::Golden => "golden",
+            FoodKind::Shrink => "shrink",
+            FoodKind::Slow => "slow",
+            FoodKind::Poison => "poison",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FoodKind> {
+        FoodKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == name)
+    }
+
+    pub fn spec(&self) -> &'static FoodSpec {
+        FOOD_TABLE
+            .iter()
+            .find(|spec| spec.kind == *self)
+            .expect("Every kind of food is in FOOD_TABLE")
+    }
+}
+
+/// Something that happens to the snake that eats a piece of food. Each is
+/// carried out by the event `food_effect_system` turns it into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FoodEffect {
+    /// Adds a segment to the end of the tail.
+    Grow,
+    /// Takes this many segments off the end of the tail, but always leaves
+    /// one.
+    Shrink(usize),
+    /// Moves take `factor` times as long for the next `seconds`.
+    SlowTime { factor: f64, seconds: f64 },
+    /// Kills the snake that ate it, where crashing kills.
+    Poison,
+}
+
+/// Everything about one kind of food.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FoodSpec {
+    pub kind: FoodKind,
+    /// What it is drawn with.
+    pub material: &'static str,
+    /// How likely it is to be picked when food spawns, against the weights
+    /// of the other kinds.
+    pub weight: u32,
+    /// Points for eating it, before the mode's `food_points` multiplier.
+    pub points: i32,
+    /// Seconds it lasts uneaten. `None` leaves it to the rules'
+    /// `food_lifetime`.
+    pub lifetime: Option<f64>,
+    pub effects: &'static [FoodEffect],
+}
+
+impl FoodSpec {
+    /// Whether a snake is better off never eating it.
+    pub fn harmful(&self) -> bool {
+        self.effects.contains(&FoodEffect::Poison)
+    }
+}
+
+pub const FOOD_TABLE: [FoodSpec; 5] = [
+    FoodSpec {
+        kind: FoodKind::Plain,
+        material: "Food",
+        weight: 80,
+        points: 1,
+        lifetime: None,
+        effects: &[FoodEffect::Grow],
+    },
+    FoodSpec {
+        kind: FoodKind::Golden,
+        material: "GoldenFood",
+        weight: 6,
+        points: 5,
+        lifetime: Some(8.),
+        effects: &[FoodEffect::Grow],
+    },
+    FoodSpec {
+        kind: FoodKind::Shrink,
+        material: "ShrinkFood",
+        weight: 6,
+        points: 1,
+        lifetime: None,
+        effects: &[FoodEffect::Shrink(3)],
+    },
+    FoodSpec {
+        kind: FoodKind::Slow,
+        material: "SlowFood",
+        weight: 5,
+        points: 1,
+        lifetime: None,
+        effects: &[
+            FoodEffect::Grow,
+            FoodEffect::SlowTime {
+                factor: 1.5,
+                seconds: 5.,
+            },
+        ],
+    },
+    FoodSpec {
+        kind: FoodKind::Poison,
+        material: "PoisonFood",
+        weight: 3,
+        points: 0,
+        lifetime: None,
+        effects: &[FoodEffect::Poison],
+    },
+];
+
+/// Picks the kind of the next piece of food by the weights in `FOOD_TABLE`.
+pub fn pick(rng: &mut Rng) -> FoodKind {
+    let total: u32 = FOOD_TABLE.iter().map(|spec| spec.weight).sum();
+    let mut roll = rng.gen_range(0, total as i32) as u32;
+    for spec in FOOD_TABLE.iter() {
+        if roll < spec.weight {
+            return spec.kind;
+        }
+        roll -= spec.weight;
+    }
+    FoodKind::Plain
+}
+
+/// A stretch of time in which moves take longer, from eating slow food.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowMotion {
+    pub factor: f64,
+    pub timer: Timer,
+}
+
+impl SlowMotion {
+    pub fn new(factor: f64, seconds: f64) -> Self {
+        Self {
+            factor,
+            timer: Timer::new(seconds),
+        }
+    }
+}
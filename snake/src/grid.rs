@@ -0,0 +1,51 @@
+// TODO(jhurstwright): Replace with no_std hashmap
+use std::collections::HashMap;
+
+use crate::components::GridPos;
+
+/// Which entities sit on each cell. Every system that spawns, moves or
+/// despawns an entity with a `GridPos` keeps this in step, so collision and
+/// food placement are cell lookups instead of scans over the world.
+#[derive(Debug, Default)]
+pub struct SpatialGrid {
+    cells: HashMap<GridPos, Vec<hecs::Entity>>,
+}
+
+impl SpatialGrid {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn insert(&mut self, entity: hecs::Entity, pos: GridPos) {
+        self.cells.entry(pos).or_default().push(entity);
+    }
+
+    pub fn remove(&mut self, entity: hecs::Entity, pos: GridPos) {
+        if let Some(entities) = self.cells.get_mut(&pos) {
+            entities.retain(|e| *e != entity);
+            if entities.is_empty() {
+                self.cells.remove(&pos);
+            }
+        }
+    }
+
+    pub fn relocate(&mut self, entity: hecs::Entity, from: GridPos, to: GridPos) {
+        if from == to {
+            return;
+        }
+        self.remove(entity, from);
+        self.insert(entity, to);
+    }
+
+    pub fn at(&self, pos: GridPos) -> &[hecs::Entity] {
+        self.cells.get(&pos).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn is_occupied(&self, pos: GridPos) -> bool {
+        self.cells.contains_key(&pos)
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+    }
+}
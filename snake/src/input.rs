@@ -0,0 +1,210 @@
+use crate::components::Direction;
+
+/// Something the player can ask for, whatever key or button they used. The
+/// plain moves belong to the first player and steer menus, the second player
+/// of a shared keyboard has moves of their own.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Pause,
+    Confirm,
+    Back,
+    Player2Left,
+    Player2Right,
+    Player2Up,
+    Player2Down,
+}
+
+impl Action {
+    pub const ALL: [Action; 11] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Pause,
+        Action::Confirm,
+        Action::Back,
+        Action::Player2Left,
+        Action::Player2Right,
+        Action::Player2Up,
+        Action::Player2Down,
+    ];
+
+    /// The left, right, up and down moves of each player that can share a
+    /// keyboard.
+    pub const MOVES: [[Action; 4]; 2] = [
+        [
+            Action::MoveLeft,
+            Action::MoveRight,
+            Action::MoveUp,
+            Action::MoveDown,
+        ],
+        [
+            Action::Player2Left,
+            Action::Player2Right,
+            Action::Player2Up,
+            Action::Player2Down,
+        ],
+    ];
+
+    /// Most players that can play at once, one for each set of `MOVES`.
+    /// Replays and bots steer through those moves, so a player past them
+    /// couldn't be recorded or steered.
+    pub const MAX_PLAYERS: u8 = Action::MOVES.len() as u8;
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::Pause => "pause",
+            Action::Confirm => "confirm",
+            Action::Back => "back",
+            Action::Player2Left => "p2_move_left",
+            Action::Player2Right => "p2_move_right",
+            Action::Player2Up => "p2_move_up",
+            Action::Player2Down => "p2_move_down",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name() == name)
+    }
+
+    /// What to call the action in menus.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::MoveLeft => "Move Left",
+            Action::MoveRight => "Move Right",
+            Action::MoveUp => "Move Up",
+            Action::MoveDown => "Move Down",
+            Action::Pause => "Pause",
+            Action::Confirm => "Confirm",
+            Action::Back => "Back",
+            Action::Player2Left => "P2 Left",
+            Action::Player2Right => "P2 Right",
+            Action::Player2Up => "P2 Up",
+            Action::Player2Down => "P2 Down",
+        }
+    }
+
+    fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+impl From<Direction> for Action {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::Left => Action::MoveLeft,
+            Direction::Right => Action::MoveRight,
+            Direction::Up => Action::MoveUp,
+            Direction::Down => Action::MoveDown,
+        }
+    }
+}
+
+/// The actions pressed since the last time the set was cleared.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ActionSet(u16);
+
+impl ActionSet {
+    pub fn insert(&mut self, action: Action) {
+        self.0 |= action.bit();
+    }
+
+    pub fn remove(&mut self, action: Action) {
+        self.0 &= !action.bit();
+    }
+
+    pub fn contains(&self, action: Action) -> bool {
+        self.0 & action.bit() != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// Everything the player did this frame, along with the size of the window
+/// they did it in.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Input {
+    pub actions: ActionSet,
+    pub width: f32,
+    pub height: f32,
+    pub resized: bool,
+}
+
+impl Input {
+    pub fn press(&mut self, action: Action) {
+        self.actions.insert(action);
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.actions.contains(action)
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self {
+            width: self.width,
+            height: self.height,
+            ..Default::default()
+        }
+    }
+
+    /// The first player's move, which is also the one menus listen to.
+    pub fn direction(&self) -> Option<Direction> {
+        self.player_direction(0)
+    }
+
+    /// The way `player` asked to turn, if they did. Players past the ones
+    /// that fit on a keyboard never ask.
+    pub fn player_direction(&self, player: u8) -> Option<Direction> {
+        let [left, right, up, down] = Action::MOVES.get(player as usize)?;
+        if self.pressed(*left) {
+            Some(Direction::Left)
+        } else if self.pressed(*right) {
+            Some(Direction::Right)
+        } else if self.pressed(*down) {
+            Some(Direction::Down)
+        } else if self.pressed(*up) {
+            Some(Direction::Up)
+        } else {
+            None
+        }
+    }
+
+    /// Presses `player`'s move towards `direction`, if they have moves.
+    pub fn press_move(&mut self, player: u8, direction: Direction) {
+        if let Some([left, right, up, down]) = Action::MOVES.get(player as usize) {
+            self.press(match direction {
+                Direction::Left => *left,
+                Direction::Right => *right,
+                Direction::Up => *up,
+                Direction::Down => *down,
+            });
+        }
+    }
+
+    /// Forgets every move `player` pressed.
+    pub fn release_moves(&mut self, player: u8) {
+        if let Some(moves) = Action::MOVES.get(player as usize) {
+            for action in moves.iter() {
+                self.actions.remove(*action);
+            }
+        }
+    }
+
+    /// Whether any player asked to turn.
+    pub fn any_direction(&self) -> bool {
+        (0..Action::MOVES.len() as u8).any(|player| self.player_direction(player).is_some())
+    }
+}
@@ -0,0 +1,507 @@
+//! Hand-drawn arenas. A level file is a few header lines followed by a map
+//! of the arena, one character per cell and the top row first:
+//!
+//! ```text
+//! snek-level 1
+//! name Pillars
+//! walls solid
+//! spawn 1 up
+//! spawn 2 down
+//! map
+//! ..........
+//! .1..##....
+//! ....##..2.
+//! ..******..
+//! ```
+//!
+//! `#` is a wall tile, `.` open floor and `*` open floor food may spawn on.
+//! Wall tiles are the only obstacles: one inside the arena is the same as
+//! its edge, and crashing into it kills the same way. A map without any `*`
+//! lets food spawn on any open floor. A digit is where that player's snake
+//! starts, heading the way its `spawn` line says, or up without one. Every
+//! `spawn` line needs its digit on the map.
+
+// TODO(jhurstwright): Replace with no_std hashmap
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::arena::{Arena, WallMode};
+use crate::components::{Direction, GridPos};
+use crate::systems;
+
+/// Bumped whenever the layout of a level file changes.
+pub const LEVEL_VERSION: u32 = 1;
+
+const MAGIC: &str = "snek-level";
+
+/// What level files are named with.
+pub const LEVEL_EXTENSION: &str = "level";
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(io::Error),
+    MissingHeader,
+    UnsupportedVersion(u32),
+    Malformed {
+        line: usize,
+    },
+    MissingMap,
+    /// The level has spawns, but not for this player, or a `spawn` line for
+    /// a player without one on the map.
+    MissingSpawn {
+        player: u8,
+    },
+    /// This player's snake would start on or facing something solid.
+    BlockedSpawn {
+        player: u8,
+    },
+    /// There is nowhere for food to spawn.
+    NoFood,
+    /// A snake can't get to this cell, where food can spawn.
+    Unreachable(GridPos),
+}
+
+impl From<io::Error> for LevelError {
+    fn from(err: io::Error) -> Self {
+        LevelError::Io(err)
+    }
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::Io(err) => write!(f, "{}", err),
+            LevelError::MissingHeader => write!(f, "not a level file"),
+            LevelError::UnsupportedVersion(v) => write!(f, "unsupported level version {}", v),
+            LevelError::Malformed { line } => write!(f, "malformed level on line {}", line),
+            LevelError::MissingMap => write!(f, "level has no map"),
+            LevelError::MissingSpawn { player } => {
+                write!(f, "level has no spawn for player {}", player + 1)
+            }
+            LevelError::BlockedSpawn { player } => {
+                write!(f, "player {} starts somewhere blocked", player + 1)
+            }
+            LevelError::NoFood => write!(f, "level has nowhere for food to spawn"),
+            LevelError::Unreachable(pos) => {
+                write!(f, "cell {} {} can't be reached", pos.0, pos.1)
+            }
+        }
+    }
+}
+
+/// Where a snake starts and which way it heads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spawn {
+    pub pos: GridPos,
+    pub direction: Direction,
+}
+
+impl Spawn {
+    /// The cell the snake's single tail segment starts on.
+    pub fn tail(&self, arena: &Arena) -> GridPos {
+        systems::next_position(arena, self.pos, self.direction.opposite())
+    }
+}
+
+/// Where `player`'s snake starts out of `players` on an arena with no level:
+/// spread evenly across the middle row, all heading up.
+pub fn default_spawn(arena: &Arena, player: u8, players: u8) -> Spawn {
+    let (min_x, _) = arena.min();
+    let spacing = arena.width / (players as i32 + 1);
+    Spawn {
+        pos: GridPos(min_x + spacing * (player as i32 + 1), 0),
+        direction: Direction::Up,
+    }
+}
+
+/// An arena laid out by hand, with walls inside it, fixed spawns and the
+/// cells food may spawn on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+    pub name: String,
+    pub arena: Arena,
+    /// Wall tiles inside the arena.
+    pub walls: HashSet<GridPos>,
+    /// Indexed by player. Empty spreads snakes out the way `default_spawn`
+    /// does.
+    pub spawns: Vec<Spawn>,
+    /// The cells food may spawn on. Empty lets it spawn on any open floor.
+    pub food: HashSet<GridPos>,
+}
+
+impl Level {
+    /// An arena with nothing in it.
+    pub fn open(name: &str, arena: Arena) -> Self {
+        Self {
+            name: name.to_string(),
+            arena,
+            walls: HashSet::new(),
+            spawns: Vec::new(),
+            food: HashSet::new(),
+        }
+    }
+
+    /// Where `player`'s snake starts out of `players`.
+    pub fn spawn(&self, player: u8, players: u8) -> Spawn {
+        match self.spawns.get(player as usize) {
+            Some(spawn) => *spawn,
+            None => default_spawn(&self.arena, player, players),
+        }
+    }
+
+    /// Whether food may spawn on `pos`, if it is free.
+    pub fn allows_food(&self, pos: GridPos) -> bool {
+        !self.walls.contains(&pos) && (self.food.is_empty() || self.food.contains(&pos))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, LevelError> {
+        let contents = fs::read_to_string(path)?;
+        Self::parse(&contents)
+    }
+
+    /// Every level file in `dir`, sorted by file name, each with whatever
+    /// went wrong reading it.
+    pub fn load_dir<P: AsRef<Path>>(
+        dir: P,
+    ) -> io::Result<Vec<(PathBuf, Result<Level, LevelError>)>> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == LEVEL_EXTENSION))
+            .collect();
+        paths.sort();
+        Ok(paths
+            .into_iter()
+            .map(|path| {
+                let level = Level::load(&path);
+                (path, level)
+            })
+            .collect())
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, LevelError> {
+        let mut lines = contents.lines().enumerate();
+        let version = match lines
+            .next()
+            .map(|(_, l)| l.split_whitespace().collect::<Vec<_>>())
+        {
+            Some(header) if header.len() == 2 && header[0] == MAGIC => header[1]
+                .parse::<u32>()
+                .map_err(|_| LevelError::MissingHeader)?,
+            _ => return Err(LevelError::MissingHeader),
+        };
+        if version == 0 || version > LEVEL_VERSION {
+            return Err(LevelError::UnsupportedVersion(version));
+        }
+
+        let mut name = String::new();
+        let mut walls = WallMode::Solid;
+        let mut directions: HashMap<u8, Direction> = HashMap::new();
+        let mut rows: Vec<(usize, &str)> = Vec::new();
+        for (index, line) in lines.by_ref() {
+            let malformed = LevelError::Malformed { line: index + 1 };
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            match parts.as_slice() {
+                [] => {}
+                ["name", ..] => name = parts[1..].join(" "),
+                ["walls", mode] => walls = WallMode::from_name(mode).ok_or(malformed)?,
+                ["spawn", player, direction] => {
+                    match (player.parse::<u8>(), Direction::from_name(direction)) {
+                        (Ok(player), Some(direction)) if player >= 1 => {
+                            directions.insert(player - 1, direction);
+                        }
+                        _ => return Err(malformed),
+                    }
+                }
+                ["map"] => break,
+                _ => return Err(malformed),
+            }
+        }
+        for (index, line) in lines {
+            let row = line.trim_end();
+            if !row.is_empty() {
+                rows.push((index, row));
+            }
+        }
+        if rows.is_empty() {
+            return Err(LevelError::MissingMap);
+        }
+
+        let width = rows[0].1.chars().count() as i32;
+        let height = rows.len() as i32;
+        let arena = Arena::new(width, height, walls);
+        let (min_x, min_y) = arena.min();
+        let mut level = Level::open(&name, arena);
+        let mut spawns: HashMap<u8, GridPos> = HashMap::new();
+        for (row, (index, line)) in rows.iter().enumerate() {
+            let malformed = || LevelError::Malformed { line: index + 1 };
+            if line.chars().count() as i32 != width {
+                return Err(malformed());
+            }
+            // The first row is the top of the arena.
+            let y = min_y + height - 1 - row as i32;
+            for (column, cell) in line.chars().enumerate() {
+                let pos = GridPos(min_x + column as i32, y);
+                match cell {
+                    '.' => {}
+                    '#' => {
+                        level.walls.insert(pos);
+                    }
+                    '*' => {
+                        level.food.insert(pos);
+                    }
+                    '1'..='9' => {
+                        let player = cell as u8 - b'1';
+                        if spawns.insert(player, pos).is_some() {
+                            return Err(malformed());
+                        }
+                    }
+                    _ => return Err(malformed()),
+                }
+            }
+        }
+        // Players are numbered from 1 with none left out.
+        for player in 0..spawns.len() as u8 {
+            let pos = *spawns
+                .get(&player)
+                .ok_or(LevelError::MissingSpawn { player })?;
+            let direction = directions.get(&player).copied().unwrap_or(Direction::Up);
+            level.spawns.push(Spawn { pos, direction });
+        }
+        if let Some(player) = directions
+            .keys()
+            .copied()
+            .filter(|player| *player as usize >= level.spawns.len())
+            .min()
+        {
+            return Err(LevelError::MissingSpawn { player });
+        }
+        Ok(level)
+    }
+
+    /// Checks the level can be played by `players` snakes: each has room to
+    /// start and make its first move, there is somewhere for food, and every
+    /// cell food can spawn on is in reach of every snake.
+    pub fn validate(&self, players: u8) -> Result<(), LevelError> {
+        let blocked = |pos: GridPos| !self.arena.contains(pos) || self.walls.contains(&pos);
+        let mut taken: HashSet<GridPos> = HashSet::new();
+        let mut spawns = Vec::with_capacity(players as usize);
+        for player in 0..players {
+            if !self.spawns.is_empty() && player as usize >= self.spawns.len() {
+                return Err(LevelError::MissingSpawn { player });
+            }
+            let spawn = self.spawn(player, players);
+            let ahead = systems::next_position(&self.arena, spawn.pos, spawn.direction);
+            let cells = [spawn.pos, spawn.tail(&self.arena)];
+            if cells.iter().chain(Some(&ahead)).any(|pos| blocked(*pos))
+                || cells.iter().any(|pos| !taken.insert(*pos))
+            {
+                return Err(LevelError::BlockedSpawn { player });
+            }
+            spawns.push(spawn);
+        }
+
+        let food: Vec<GridPos> = self
+            .arena
+            .cells()
+            .filter(|pos| self.allows_food(*pos) && !taken.contains(pos))
+            .collect();
+        if food.is_empty() {
+            return Err(LevelError::NoFood);
+        }
+
+        for spawn in spawns.iter() {
+            let reached = self.reachable(spawn.pos, &blocked);
+            if let Some(pos) = food.iter().find(|pos| !reached.contains(pos)) {
+                return Err(LevelError::Unreachable(*pos));
+            }
+        }
+        Ok(())
+    }
+
+    /// Every cell that can be walked to from `start`.
+    fn reachable(&self, start: GridPos, blocked: &dyn Fn(GridPos) -> bool) -> HashSet<GridPos> {
+        let mut reached: HashSet<GridPos> = HashSet::new();
+        let mut open = VecDeque::new();
+        reached.insert(start);
+        open.push_back(start);
+        while let Some(pos) = open.pop_front() {
+            for direction in Direction::ALL.iter() {
+                let next = systems::next_position(&self.arena, pos, *direction);
+                if !blocked(next) && reached.insert(next) {
+                    open.push_back(next);
+                }
+            }
+        }
+        reached
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", MAGIC, LEVEL_VERSION)?;
+        writeln!(f, "name {}", self.name)?;
+        writeln!(f, "walls {}", self.arena.walls.name())?;
+        for (player, spawn) in self.spawns.iter().enumerate() {
+            writeln!(f, "spawn {} {}", player + 1, spawn.direction.name())?;
+        }
+        writeln!(f, "map")?;
+        let (min_x, _) = self.arena.min();
+        let (_, max_y) = self.arena.max();
+        for y in (max_y - self.arena.height..max_y).rev() {
+            for x in min_x..min_x + self.arena.width {
+                let pos = GridPos(x, y);
+                let cell = match self.spawns.iter().position(|spawn| spawn.pos == pos) {
+                    Some(player) => (b'1' + player as u8) as char,
+                    None if self.walls.contains(&pos) => '#',
+                    None if self.food.contains(&pos) => '*',
+                    None => '.',
+                };
+                write!(f, "{}", cell)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes `level` into another file, as a `level <lines>` line followed by
+/// that many lines of level file, or as `level none`.
+pub(crate) fn write_embedded(f: &mut fmt::Formatter<'_>, level: Option<&Level>) -> fmt::Result {
+    match level {
+        Some(level) => {
+            let text = level.to_string();
+            writeln!(f, "level {}", text.lines().count())?;
+            write!(f, "{}", text)
+        }
+        None => writeln!(f, "level none"),
+    }
+}
+
+/// Reads back what `write_embedded` wrote, given what followed `level` on
+/// its line and the lines after it. `None` if it is malformed.
+pub(crate) fn read_embedded<'a, I>(count: &str, lines: &mut I) -> Option<Option<Level>>
+where
+    I: Iterator<Item = (usize, &'a str)>,
+{
+    if count == "none" {
+        return Some(None);
+    }
+    let count: usize = count.parse().ok()?;
+    let text: Vec<&str> = lines.take(count).map(|(_, line)| line).collect();
+    if text.len() != count {
+        return None;
+    }
+    Level::parse(&text.join("\n")).ok().map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A level file with `headers` between its version and its map.
+    fn parse(headers: &str, map: &str) -> Result<Level, LevelError> {
+        Level::parse(&format!(
+            "{} {}\n{}\nmap\n{}",
+            MAGIC, LEVEL_VERSION, headers, map
+        ))
+    }
+
+    const ROOMS: &str = "\
+.......#....
+.1.....#.**.
+.......#.**.
+.......#....
+";
+
+    #[test]
+    fn a_level_reads_back_what_it_wrote() {
+        let level = parse("name Rooms\nwalls wrap\nspawn 1 down", ROOMS).unwrap();
+        assert_eq!(level.arena.width, 12);
+        assert_eq!(level.arena.height, 4);
+        assert_eq!(level.walls.len(), 4);
+        assert_eq!(level.food.len(), 4);
+        assert_eq!(level.spawns[0].direction, Direction::Down);
+        assert_eq!(Level::parse(&level.to_string()).unwrap(), level);
+    }
+
+    #[test]
+    fn food_walled_off_from_a_snake_is_unreachable() {
+        let level = parse("walls solid", ROOMS).unwrap();
+        assert!(matches!(
+            level.validate(1),
+            Err(LevelError::Unreachable(pos)) if level.food.contains(&pos)
+        ));
+
+        // Wrapping round the edge gets there the other way.
+        let level = parse("walls wrap", ROOMS).unwrap();
+        assert!(level.validate(1).is_ok());
+    }
+
+    #[test]
+    fn a_snake_starting_against_a_wall_is_blocked() {
+        let map = "\
+..#..
+..1..
+.....
+";
+        let level = parse("spawn 1 up", map).unwrap();
+        assert!(matches!(
+            level.validate(1),
+            Err(LevelError::BlockedSpawn { player: 0 })
+        ));
+        let level = parse("spawn 1 left", map).unwrap();
+        assert!(level.validate(1).is_ok());
+        // The second snake's tail would start on the first's head.
+        let level = parse("spawn 1 left", "..2..\n..1..\n.....\n").unwrap();
+        assert!(matches!(
+            level.validate(2),
+            Err(LevelError::BlockedSpawn { player: 1 })
+        ));
+    }
+
+    #[test]
+    fn a_spawn_line_needs_its_digit_on_the_map() {
+        assert!(matches!(
+            parse("spawn 1 up\nspawn 2 down", ".1..\n....\n....\n"),
+            Err(LevelError::MissingSpawn { player: 1 })
+        ));
+        assert!(matches!(
+            parse("spawn 1 up", "....\n....\n....\n"),
+            Err(LevelError::MissingSpawn { player: 0 })
+        ));
+        // Nor can the map skip a player.
+        assert!(matches!(
+            parse("", ".1..\n...3\n....\n"),
+            Err(LevelError::MissingSpawn { player: 1 })
+        ));
+    }
+
+    #[test]
+    fn an_arena_needs_even_rows_and_room_for_its_snakes() {
+        // Line 5 of the file is the second row of the map, one short.
+        assert!(matches!(
+            parse("walls solid", ".....\n....\n.....\n"),
+            Err(LevelError::Malformed { line: 5 })
+        ));
+        assert!(matches!(
+            parse("walls solid", ""),
+            Err(LevelError::MissingMap)
+        ));
+        // No room behind the snake for its tail.
+        let level = parse("walls solid", ".1.\n").unwrap();
+        assert!(matches!(
+            level.validate(1),
+            Err(LevelError::BlockedSpawn { player: 0 })
+        ));
+        // Without a level's spawns the snakes spread along the middle row,
+        // and two of them don't fit on an arena this narrow.
+        let level = parse("walls solid", "..\n..\n..\n").unwrap();
+        assert!(matches!(
+            level.validate(2),
+            Err(LevelError::BlockedSpawn { .. })
+        ));
+    }
+}
@@ -0,0 +1,19 @@
+//! The rules of Snek. Nothing in here needs a window or a GPU, so bots,
+//! servers and tests can step a `GameWorld` directly.
+pub mod ai;
+pub mod arena;
+pub mod batch;
+pub mod clock;
+pub mod components;
+pub mod devices;
+pub mod events;
+pub mod food;
+pub mod grid;
+pub mod input;
+pub mod level;
+pub mod mode;
+pub mod net;
+pub mod replay;
+pub mod rng;
+pub mod snapshot;
+pub mod systems;
@@ -0,0 +1,243 @@
+use std::fmt;
+
+use crate::arena::{Arena, WallMode};
+use crate::food::FoodKind;
+
+/// The rule sets a run can be played with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GameMode {
+    /// Eat, grow, don't crash.
+    #[default]
+    Classic,
+    /// As much food as possible before the clock runs out.
+    TimeAttack,
+    /// The snake speeds up as it grows.
+    Survival,
+    /// Nothing kills. Running into the tail bites the rest of it off.
+    Zen,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 4] = [
+        GameMode::Classic,
+        GameMode::TimeAttack,
+        GameMode::Survival,
+        GameMode::Zen,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Classic => "classic",
+            GameMode::TimeAttack => "time_attack",
+            GameMode::Survival => "survival",
+            GameMode::Zen => "zen",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<GameMode> {
+        GameMode::ALL
+            .iter()
+            .copied()
+            .find(|mode| mode.name() == name)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GameMode::Classic => "Classic",
+            GameMode::TimeAttack => "Time Attack",
+            GameMode::Survival => "Survival",
+            GameMode::Zen => "Zen",
+        }
+    }
+
+    pub fn rules(&self) -> Rules {
+        let classic = Rules::default();
+        match self {
+            GameMode::Classic => classic,
+            GameMode::TimeAttack => Rules {
+                time_limit: Some(120.),
+                food_time: 1.,
+                varied_food: true,
+                food_lifetime: Some(20.),
+                ..classic
+            },
+            GameMode::Survival => Rules {
+                speed: SpeedCurve::Linear { step: 0.005 },
+                food_points: 2,
+                varied_food: true,
+                food_lifetime: Some(20.),
+                ..classic
+            },
+            GameMode::Zen => Rules {
+                deadly: false,
+                varied_food: true,
+                food_lifetime: Some(20.),
+                ..classic
+            },
+        }
+    }
+}
+
+/// Everything a `GameMode` decides about how a run plays.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rules {
+    /// Seconds between moves at the start of a run.
+    pub move_time: f64,
+    /// How the time between moves shrinks as the run goes on.
+    pub speed: SpeedCurve,
+    /// What counts as going on.
+    pub speed_basis: SpeedBasis,
+    /// The curve never takes the time between moves below this.
+    pub min_move_time: f64,
+    /// Seconds between food spawns.
+    pub food_time: f64,
+    /// Food stops spawning while there is this much on the board.
+    pub max_food: usize,
+    /// Seconds food lasts uneaten, for kinds without a lifetime of their
+    /// own. `None` leaves it there for good.
+    pub food_lifetime: Option<f64>,
+    /// Seconds before food runs out that it starts blinking.
+    pub food_warning: f64,
+    /// Multiplies the points every piece of food is worth.
+    pub food_points: i32,
+    /// Whether food other than plain food spawns.
+    pub varied_food: bool,
+    /// Runs end after this many seconds.
+    pub time_limit: Option<f64>,
+    /// Whether crashing kills. When it doesn't the arena wraps around, and a
+    /// snake that runs into its own tail loses the part it ran into.
+    pub deadly: bool,
+}
+
+impl Default for Rules {
+    /// The classic rules.
+    fn default() -> Self {
+        Self {
+            move_time: 0.25,
+            speed: SpeedCurve::Constant,
+            speed_basis: SpeedBasis::Length,
+            min_move_time: 0.08,
+            food_time: 1.5,
+            max_food: 10,
+            food_lifetime: None,
+            food_warning: 3.,
+            food_points: 1,
+            varied_food: false,
+            time_limit: None,
+            deadly: true,
+        }
+    }
+}
+
+impl Rules {
+    /// `arena` as these rules play it. Where crashing doesn't kill, its
+    /// edges wrap around whatever it was set up with.
+    pub fn play_arena(&self, arena: Arena) -> Arena {
+        let walls = if self.deadly {
+            arena.walls
+        } else {
+            WallMode::Wrap
+        };
+        Arena { walls, ..arena }
+    }
+
+    /// Seconds food of `kind` lasts uneaten, if it doesn't stay for good.
+    pub fn food_lifetime(&self, kind: FoodKind) -> Option<f64> {
+        kind.spec().lifetime.or(self.food_lifetime)
+    }
+
+    /// Seconds between moves once the run has made `progress`, counted in
+    /// `speed_basis`. The floor never makes the pace slower than it started.
+    pub fn move_time_at(&self, progress: u32) -> f64 {
+        let floor = self.min_move_time.min(self.move_time);
+        self.speed.apply(self.move_time, progress).max(floor)
+    }
+}
+
+/// How the time between moves follows progress through a run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpeedCurve {
+    /// The same pace all run.
+    Constant,
+    /// `step` seconds faster for every point of progress.
+    Linear { step: f64 },
+    /// `step` seconds faster every `every` points of progress.
+    Stepped { every: u32, step: f64 },
+    /// Every point of progress multiplies the time between moves by
+    /// `factor`.
+    Exponential { factor: f64 },
+}
+
+impl SpeedCurve {
+    /// The time between moves after `progress`, starting from `move_time`
+    /// and before any floor.
+    pub fn apply(&self, move_time: f64, progress: u32) -> f64 {
+        let progress_f = progress as f64;
+        match *self {
+            SpeedCurve::Constant => move_time,
+            SpeedCurve::Linear { step } => move_time - step * progress_f,
+            SpeedCurve::Stepped { every, step } => {
+                move_time - step * (progress / every.max(1)) as f64
+            }
+            SpeedCurve::Exponential { factor } => move_time * factor.powf(progress_f),
+        }
+    }
+
+    /// Reads a curve written the way `Display` writes it, such as
+    /// `linear:0.005` or `stepped:5:0.02`.
+    pub fn parse(text: &str) -> Option<SpeedCurve> {
+        let parts: Vec<&str> = text.split(':').collect();
+        match parts.as_slice() {
+            ["constant"] => Some(SpeedCurve::Constant),
+            ["linear", step] => Some(SpeedCurve::Linear {
+                step: step.parse().ok()?,
+            }),
+            ["stepped", every, step] => Some(SpeedCurve::Stepped {
+                every: every.parse().ok().filter(|every| *every > 0)?,
+                step: step.parse().ok()?,
+            }),
+            ["exponential", factor] => Some(SpeedCurve::Exponential {
+                factor: factor.parse().ok().filter(|factor| *factor > 0.)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SpeedCurve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpeedCurve::Constant => write!(f, "constant"),
+            SpeedCurve::Linear { step } => write!(f, "linear:{}", step),
+            SpeedCurve::Stepped { every, step } => write!(f, "stepped:{}:{}", every, step),
+            SpeedCurve::Exponential { factor } => write!(f, "exponential:{}", factor),
+        }
+    }
+}
+
+/// What a `SpeedCurve` measures progress in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeedBasis {
+    /// Segments the longest snake still going has grown by.
+    Length,
+    /// The best score so far.
+    Score,
+}
+
+impl SpeedBasis {
+    pub const ALL: [SpeedBasis; 2] = [SpeedBasis::Length, SpeedBasis::Score];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpeedBasis::Length => "length",
+            SpeedBasis::Score => "score",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SpeedBasis> {
+        SpeedBasis::ALL
+            .iter()
+            .copied()
+            .find(|basis| basis.name() == name)
+    }
+}
@@ -0,0 +1,875 @@
+//! Two player games over UDP. Both peers run the whole simulation and only
+//! trade input: a tick is stepped once both players' input for it has
+//! arrived, so the two `GameWorld`s stay identical as long as the simulation
+//! is deterministic. Hashes of the world are traded as well to catch the
+//! moment they stop being.
+//!
+//! Local input is scheduled a few ticks ahead to give it time to reach the
+//! peer, and every packet repeats all the input the peer hasn't acknowledged
+//! yet, so a lost packet costs nothing but a little delay.
+//!
+//! Before the first tick the guest picks a session and the host answers it
+//! with the seed. Packets carry a hash of the game's settings, and a peer
+//! started with different ones is never connected to.
+
+// TODO(jhurstwright): Replace with no_std hashmap
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::arena::Arena;
+use crate::components::{self, Input};
+use crate::food::FoodKind;
+use crate::level::Level;
+use crate::mode::GameMode;
+use crate::replay;
+use crate::rng::Rng;
+use crate::systems::GameWorld;
+
+const MAGIC: &[u8; 4] = b"SNK2";
+/// Largest packet either side sends.
+const MAX_PACKET: usize = 512;
+/// Most inputs repeated in a single packet.
+const MAX_INPUTS: usize = 128;
+/// World hashes are traded every this many ticks.
+pub const HASH_INTERVAL: u32 = 30;
+
+/// Moves packets between the two peers. Neither call may block.
+pub trait Transport {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()>;
+    /// Copies the next packet that arrived into `buf`, if there is one, and
+    /// returns its length.
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>>;
+}
+
+/// A non-blocking UDP socket that only talks to one peer.
+pub struct UdpTransport {
+    socket: UdpSocket,
+}
+
+impl UdpTransport {
+    pub fn bind(local: SocketAddr, peer: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(peer)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket })
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        match self.socket.send(packet) {
+            Ok(_) => Ok(()),
+            // Nobody is listening on the other end yet. Input is resent until
+            // it is acknowledged, so this packet isn't missed.
+            Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        loop {
+            return match self.socket.recv(buf) {
+                Ok(len) => Ok(Some(len)),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(None),
+                Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => continue,
+                Err(err) => Err(err),
+            };
+        }
+    }
+}
+
+/// Makes a transport worse on purpose. Outgoing packets are dropped at
+/// random or held back for a while before they are really sent, to try out
+/// lockstep on loopback as if it were a bad connection.
+pub struct LossyTransport<T: Transport> {
+    inner: T,
+    /// Share of packets dropped, from 0 to 1.
+    pub loss: f64,
+    /// Seconds every packet is held back.
+    pub latency: f64,
+    /// Up to this many more seconds are added at random on top of `latency`,
+    /// so packets can arrive out of order.
+    pub jitter: f64,
+    rng: Rng,
+    delayed: Vec<(Instant, Vec<u8>)>,
+}
+
+impl<T: Transport> LossyTransport<T> {
+    pub fn new(inner: T, loss: f64, latency: f64, jitter: f64, seed: u64) -> Self {
+        Self {
+            inner,
+            loss,
+            latency,
+            jitter,
+            rng: Rng::new(seed),
+            delayed: Vec::new(),
+        }
+    }
+
+    /// A number in `0..1`.
+    fn roll(&mut self) -> f64 {
+        self.rng.gen_range(0, 1 << 20) as f64 / (1 << 20) as f64
+    }
+
+    /// Sends everything that has been held back long enough.
+    fn flush(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        let (due, waiting): (Vec<_>, Vec<_>) =
+            self.delayed.drain(..).partition(|(at, _)| *at <= now);
+        self.delayed = waiting;
+        for (_, packet) in due {
+            self.inner.send(&packet)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Transport> Transport for LossyTransport<T> {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        if self.roll() >= self.loss {
+            let delay = self.latency + self.jitter * self.roll();
+            let at = Instant::now() + Duration::from_secs_f64(delay);
+            self.delayed.push((at, packet.to_vec()));
+        }
+        self.flush()
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        self.flush()?;
+        self.inner.recv(buf)
+    }
+}
+
+/// Everything needed to start a networked game from the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetConfig {
+    pub local: SocketAddr,
+    pub peer: SocketAddr,
+    /// 0 hosts the game and picks the seed, 1 joins it.
+    pub player: u8,
+    /// Ticks local input is held back to give it time to reach the peer.
+    pub delay: u32,
+    /// Packet loss, latency and jitter to fake, see `LossyTransport`.
+    pub loss: f64,
+    pub latency: f64,
+    pub jitter: f64,
+}
+
+impl NetConfig {
+    pub fn new(local: SocketAddr, peer: SocketAddr, player: u8) -> Self {
+        Self {
+            local,
+            peer,
+            player,
+            delay: 4,
+            loss: 0.,
+            latency: 0.,
+            jitter: 0.,
+        }
+    }
+
+    /// Opens the socket and starts a session. The host's `seed` is the one
+    /// both sides play with, the guest's is ignored. Once connected,
+    /// `Lockstep::seed` has the one to use. `settings` is the
+    /// `settings_hash` of the game, which has to match the peer's.
+    pub fn open(&self, seed: u64, settings: u64) -> io::Result<Lockstep> {
+        let udp = UdpTransport::bind(self.local, self.peer)?;
+        let transport: Box<dyn Transport> =
+            if self.loss > 0. || self.latency > 0. || self.jitter > 0. {
+                Box::new(LossyTransport::new(
+                    udp,
+                    self.loss,
+                    self.latency,
+                    self.jitter,
+                    seed ^ self.player as u64,
+                ))
+            } else {
+                Box::new(udp)
+            };
+        // Anything that differs from the guest's last session will do.
+        let session = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_nanos() as u64)
+            .unwrap_or(seed)
+            ^ self.local.port() as u64;
+        Ok(Lockstep::new(
+            transport,
+            self.player,
+            seed,
+            self.delay,
+            settings,
+            session,
+        ))
+    }
+}
+
+/// A hash of the settings both peers have to start with to play the same
+/// game. Only meaningful between copies of the same build.
+pub fn settings_hash(mode: GameMode, arena: &Arena, players: u8, level: Option<&Level>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    mode.name().hash(&mut hasher);
+    (arena.width, arena.height, arena.walls.name()).hash(&mut hasher);
+    players.hash(&mut hasher);
+    level.map(Level::to_string).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A hash of everything that decides how the game plays out from here.
+/// Only meaningful between copies of the same build.
+pub fn state_hash(game_world: &GameWorld) -> u64 {
+    let mut cells: Vec<(components::GridPos, u8, usize, Option<FoodKind>, bool)> = game_world
+        .world
+        .query::<(
+            &components::GridPos,
+            Option<&components::Player>,
+            Option<&components::Tail>,
+            Option<&components::Food>,
+            Option<&components::Dead>,
+        )>()
+        .iter()
+        .map(|(_, (pos, player, tail, food, dead))| {
+            (
+                *pos,
+                player.map(|player| player.0 + 1).unwrap_or(0),
+                tail.map(|tail| tail.segment).unwrap_or(0),
+                food.map(|food| food.0),
+                dead.is_some(),
+            )
+        })
+        .collect();
+    cells.sort_by_key(|(pos, player, segment, _, dead)| (pos.0, pos.1, *player, *segment, *dead));
+    let mut hasher = DefaultHasher::new();
+    cells.hash(&mut hasher);
+    game_world.rng.snapshot().0.hash(&mut hasher);
+    game_world.move_timer.elapsed().to_bits().hash(&mut hasher);
+    game_world.food_timer.elapsed().to_bits().hash(&mut hasher);
+    if let Some(slow_motion) = &game_world.slow_motion {
+        slow_motion.timer.elapsed().to_bits().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Where two peers' worlds were first seen to differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Desync {
+    pub tick: u32,
+    pub local: u64,
+    pub remote: u64,
+}
+
+/// What a packet says. All numbers are little endian.
+///
+/// ```text
+/// "SNK2" player:u8 settings:u64 session:u64 seed:u64 ack:u32
+/// hash_tick:u32 hash:u64 first_tick:u32 count:u8 input:u8 * count
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+struct Packet {
+    player: u8,
+    /// The sender's `settings_hash`.
+    settings: u64,
+    /// The guest's session, 0 from a host that hasn't heard from one.
+    session: u64,
+    /// The seed the sender plays with, 0 from a guest that hasn't heard it.
+    seed: u64,
+    /// The sender has every input from the receiver before this tick.
+    ack: u32,
+    hash_tick: u32,
+    /// `None` until the sender has stepped a tick worth hashing.
+    hash: Option<u64>,
+    first_tick: u32,
+    inputs: Vec<u8>,
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if bytes.len() < len {
+        return None;
+    }
+    let (head, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Some(head)
+}
+
+fn take_u32(bytes: &mut &[u8]) -> Option<u32> {
+    let mut word = [0; 4];
+    word.copy_from_slice(take(bytes, 4)?);
+    Some(u32::from_le_bytes(word))
+}
+
+fn take_u64(bytes: &mut &[u8]) -> Option<u64> {
+    let mut word = [0; 8];
+    word.copy_from_slice(take(bytes, 8)?);
+    Some(u64::from_le_bytes(word))
+}
+
+impl Packet {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(50 + self.inputs.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(self.player);
+        bytes.extend_from_slice(&self.settings.to_le_bytes());
+        bytes.extend_from_slice(&self.session.to_le_bytes());
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&self.ack.to_le_bytes());
+        // A tick of u32::MAX means no hash.
+        let hash_tick = self.hash.map(|_| self.hash_tick).unwrap_or(u32::MAX);
+        bytes.extend_from_slice(&hash_tick.to_le_bytes());
+        bytes.extend_from_slice(&self.hash.unwrap_or(0).to_le_bytes());
+        bytes.extend_from_slice(&self.first_tick.to_le_bytes());
+        bytes.push(self.inputs.len() as u8);
+        bytes.extend_from_slice(&self.inputs);
+        bytes
+    }
+
+    fn decode(mut bytes: &[u8]) -> Option<Packet> {
+        let bytes = &mut bytes;
+        if take(bytes, MAGIC.len())? != MAGIC {
+            return None;
+        }
+        let player = take(bytes, 1)?[0];
+        let settings = take_u64(bytes)?;
+        let session = take_u64(bytes)?;
+        let seed = take_u64(bytes)?;
+        let ack = take_u32(bytes)?;
+        let hash_tick = take_u32(bytes)?;
+        let hash = take_u64(bytes)?;
+        let first_tick = take_u32(bytes)?;
+        let count = take(bytes, 1)?[0] as usize;
+        let inputs = take(bytes, count)?.to_vec();
+        Some(Packet {
+            player,
+            settings,
+            session,
+            seed,
+            ack,
+            hash_tick,
+            hash: if hash_tick == u32::MAX {
+                None
+            } else {
+                Some(hash)
+            },
+            first_tick,
+            inputs,
+        })
+    }
+}
+
+/// One side of a networked game. Feed it the local player's input once per
+/// tick with `push_local` and step the world with whatever `next_input`
+/// hands back. When it hands back nothing the peer's input for the tick
+/// hasn't arrived, and the world has to wait.
+pub struct Lockstep {
+    transport: Box<dyn Transport>,
+    player: u8,
+    settings: u64,
+    /// Picked by the guest. 0 while the host is still waiting to hear it.
+    session: u64,
+    /// 0 while a guest is still waiting to hear it from the host.
+    seed: u64,
+    delay: u32,
+    /// The next tick to be stepped.
+    tick: u32,
+    /// Local input by tick, from the oldest the peer hasn't acknowledged.
+    local: VecDeque<u8>,
+    /// The tick of the front of `local`.
+    local_start: u32,
+    /// Input the local player pressed while the world was waiting.
+    pending: u8,
+    remote: HashMap<u32, u8>,
+    /// Every remote input before this tick has arrived.
+    remote_next: u32,
+    /// Local hashes waiting for the peer's and the peer's waiting for ours.
+    local_hashes: HashMap<u32, u64>,
+    remote_hashes: HashMap<u32, u64>,
+    last_hash: Option<(u32, u64)>,
+    connected: bool,
+    /// Set once the peer was heard from with different settings.
+    mismatch: bool,
+    desync: Option<Desync>,
+}
+
+impl Lockstep {
+    /// `session` only matters on the guest, and has to differ from any it
+    /// used with the same host before.
+    pub fn new(
+        transport: Box<dyn Transport>,
+        player: u8,
+        seed: u64,
+        delay: u32,
+        settings: u64,
+        session: u64,
+    ) -> Self {
+        let mut remote = HashMap::new();
+        // Nobody pressed anything in the ticks before input could arrive.
+        for tick in 0..delay {
+            remote.insert(tick, 0);
+        }
+        Self {
+            transport,
+            player,
+            settings,
+            // 0 stands for a session or seed that isn't known yet.
+            session: if player == 0 { 0 } else { session.max(1) },
+            seed: if player == 0 { seed.max(1) } else { 0 },
+            delay,
+            tick: 0,
+            local: (0..delay).map(|_| 0).collect(),
+            local_start: 0,
+            pending: 0,
+            remote,
+            remote_next: delay,
+            local_hashes: HashMap::new(),
+            remote_hashes: HashMap::new(),
+            last_hash: None,
+            connected: false,
+            mismatch: false,
+            desync: None,
+        }
+    }
+
+    pub fn player(&self) -> u8 {
+        self.player
+    }
+
+    /// The player on the other end.
+    pub fn peer(&self) -> u8 {
+        1 - self.player
+    }
+
+    /// The seed both sides play with, once it is known.
+    pub fn seed(&self) -> Option<u64> {
+        match self.seed {
+            0 => None,
+            seed => Some(seed),
+        }
+    }
+
+    /// Whether the peer has been heard from and the seed agreed on.
+    pub fn connected(&self) -> bool {
+        self.connected && self.seed().is_some()
+    }
+
+    /// Whether the peer was heard from but started with different settings.
+    /// It is never connected to.
+    pub fn mismatch(&self) -> bool {
+        self.mismatch
+    }
+
+    /// The next tick to be stepped.
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    pub fn desync(&self) -> Option<Desync> {
+        self.desync
+    }
+
+    /// Schedules the local player's moves `delay` ticks ahead. Moves made
+    /// while the world waits on the peer are held on to until there is room
+    /// for them.
+    pub fn push_local(&mut self, input: &Input) {
+        let bits = replay::encode(input, 0);
+        if bits != 0 {
+            self.pending = bits;
+        }
+        let next = self.local_start + self.local.len() as u32;
+        if next <= self.tick + self.delay {
+            self.local.push_back(self.pending);
+            self.pending = 0;
+        }
+    }
+
+    /// Both players' moves for the next tick, each on their own player's
+    /// actions, if they have both arrived.
+    pub fn next_input(&mut self) -> Option<Input> {
+        if !self.connected() {
+            return None;
+        }
+        let local = self
+            .tick
+            .checked_sub(self.local_start)
+            .and_then(|index| self.local.get(index as usize))
+            .copied()?;
+        let remote = self.remote.remove(&self.tick)?;
+        let mut input = Input::default();
+        replay::decode(local, self.player, &mut input);
+        replay::decode(remote, self.peer(), &mut input);
+        self.tick += 1;
+        Some(input)
+    }
+
+    /// Hands over the hash of the world after stepping the tick before
+    /// `tick()`. Only every `HASH_INTERVAL`th is kept.
+    pub fn record_hash(&mut self, hash: u64) {
+        let tick = self.tick.wrapping_sub(1);
+        if !tick.is_multiple_of(HASH_INTERVAL) {
+            return;
+        }
+        self.last_hash = Some((tick, hash));
+        match self.remote_hashes.remove(&tick) {
+            Some(remote) => self.compare(tick, hash, remote),
+            None => {
+                self.local_hashes.insert(tick, hash);
+            }
+        }
+    }
+
+    fn compare(&mut self, tick: u32, local: u64, remote: u64) {
+        if local != remote && self.desync.is_none() {
+            self.desync = Some(Desync {
+                tick,
+                local,
+                remote,
+            });
+        }
+    }
+
+    /// Reads every packet that has arrived.
+    pub fn poll(&mut self) -> io::Result<()> {
+        let mut buf = [0; MAX_PACKET];
+        while let Some(len) = self.transport.recv(&mut buf)? {
+            if let Some(packet) = Packet::decode(&buf[..len]) {
+                self.receive(packet);
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, packet: Packet) {
+        if packet.player != self.peer() {
+            return;
+        }
+        if packet.settings != self.settings {
+            self.mismatch = true;
+            return;
+        }
+        if self.player == 0 {
+            if packet.session != self.session {
+                // A guest new to this session hasn't heard the seed yet.
+                // Stray packets from an earlier one with the same peer have.
+                if packet.seed != 0 || self.connected {
+                    return;
+                }
+                self.session = packet.session;
+            }
+            // The guest is connected once it plays with our seed.
+            if packet.seed != self.seed {
+                return;
+            }
+        } else {
+            // Only the host's answers to this session count.
+            if packet.session != self.session || packet.seed == 0 {
+                return;
+            }
+            match self.seed {
+                0 => self.seed = packet.seed,
+                seed if seed != packet.seed => return,
+                _ => {}
+            }
+        }
+        self.connected = true;
+
+        for (offset, bits) in packet.inputs.iter().enumerate() {
+            let tick = packet.first_tick + offset as u32;
+            if tick >= self.tick {
+                self.remote.entry(tick).or_insert(*bits);
+            }
+        }
+        while self.remote.contains_key(&self.remote_next) {
+            self.remote_next += 1;
+        }
+
+        while self.local_start < packet.ack && !self.local.is_empty() {
+            // Still needed to step the tick it belongs to.
+            if self.local_start >= self.tick {
+                break;
+            }
+            self.local.pop_front();
+            self.local_start += 1;
+        }
+
+        if let Some(hash) = packet.hash {
+            // Older hashes were never sent and never will be.
+            self.local_hashes
+                .retain(|tick, _| *tick >= packet.hash_tick);
+            match self.local_hashes.remove(&packet.hash_tick) {
+                Some(local) => self.compare(packet.hash_tick, local, hash),
+                None if packet.hash_tick >= self.tick => {
+                    self.remote_hashes.insert(packet.hash_tick, hash);
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Sends the peer every local input it hasn't acknowledged, along with
+    /// the latest hash. Called once a frame, whether or not the world moved.
+    pub fn send(&mut self) -> io::Result<()> {
+        let inputs: Vec<u8> = self.local.iter().take(MAX_INPUTS).copied().collect();
+        let (hash_tick, hash) = match self.last_hash {
+            Some((tick, hash)) => (tick, Some(hash)),
+            None => (0, None),
+        };
+        let packet = Packet {
+            player: self.player,
+            settings: self.settings,
+            session: self.session,
+            seed: self.seed,
+            ack: self.remote_next,
+            hash_tick,
+            hash,
+            first_tick: self.local_start,
+            inputs,
+        };
+        self.transport.send(&packet.encode())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::STEP;
+    use crate::components::Direction;
+    use crate::systems;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::thread;
+
+    type Queue = Rc<RefCell<VecDeque<Vec<u8>>>>;
+
+    /// One end of a lossless link in memory.
+    struct Pipe {
+        outgoing: Queue,
+        incoming: Queue,
+    }
+
+    impl Transport for Pipe {
+        fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+            self.outgoing.borrow_mut().push_back(packet.to_vec());
+            Ok(())
+        }
+
+        fn recv(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+            Ok(self.incoming.borrow_mut().pop_front().map(|packet| {
+                buf[..packet.len()].copy_from_slice(&packet);
+                packet.len()
+            }))
+        }
+    }
+
+    /// A host seeded with 42 and a guest, and the queues of packets on their
+    /// way to each.
+    fn pair(host_settings: u64, guest_settings: u64) -> (Lockstep, Lockstep, Queue, Queue) {
+        let to_host = Queue::default();
+        let to_guest = Queue::default();
+        let host = Pipe {
+            outgoing: to_guest.clone(),
+            incoming: to_host.clone(),
+        };
+        let guest = Pipe {
+            outgoing: to_host.clone(),
+            incoming: to_guest.clone(),
+        };
+        (
+            Lockstep::new(Box::new(host), 0, 42, 2, host_settings, 0),
+            Lockstep::new(Box::new(guest), 1, 7, 2, guest_settings, 1000),
+            to_host,
+            to_guest,
+        )
+    }
+
+    fn exchange(host: &mut Lockstep, guest: &mut Lockstep) {
+        for _ in 0..4 {
+            guest.send().unwrap();
+            host.send().unwrap();
+            host.poll().unwrap();
+            guest.poll().unwrap();
+        }
+    }
+
+    fn stray(player: u8, settings: u64, session: u64, seed: u64) -> Vec<u8> {
+        Packet {
+            player,
+            settings,
+            session,
+            seed,
+            ack: 0,
+            hash_tick: 0,
+            hash: None,
+            first_tick: 0,
+            inputs: vec![0; 8],
+        }
+        .encode()
+    }
+
+    #[test]
+    fn peers_agree_on_the_host_seed() {
+        let (mut host, mut guest, _, _) = pair(5, 5);
+        exchange(&mut host, &mut guest);
+        assert!(host.connected());
+        assert!(guest.connected());
+        assert_eq!(guest.seed(), Some(42));
+    }
+
+    #[test]
+    fn peers_with_other_settings_never_connect() {
+        let (mut host, mut guest, _, _) = pair(5, 6);
+        exchange(&mut host, &mut guest);
+        assert!(!host.connected());
+        assert!(!guest.connected());
+        assert!(host.mismatch());
+        assert!(guest.mismatch());
+        assert!(guest.next_input().is_none());
+    }
+
+    #[test]
+    fn packets_from_an_earlier_session_are_ignored() {
+        let (mut host, mut guest, to_host, to_guest) = pair(5, 5);
+        to_guest.borrow_mut().push_back(stray(0, 5, 999, 1234));
+        to_host.borrow_mut().push_back(stray(1, 5, 999, 1234));
+        host.poll().unwrap();
+        guest.poll().unwrap();
+        assert!(!host.connected());
+        assert_eq!(guest.seed(), None);
+
+        exchange(&mut host, &mut guest);
+        assert!(host.connected());
+        assert_eq!(guest.seed(), Some(42));
+    }
+
+    /// One end of a game over a bad connection: its lockstep and, once the
+    /// seed is agreed on, the world it steps.
+    struct Peer {
+        lockstep: Lockstep,
+        game_world: Option<GameWorld>,
+        /// Steer both snakes round in circles for a while, behind the
+        /// peer's back.
+        swerve: bool,
+    }
+
+    impl Peer {
+        fn new(transport: impl Transport + 'static, player: u8) -> Self {
+            let session = 1000 * player as u64;
+            Self {
+                lockstep: Lockstep::new(Box::new(transport), player, 42, 3, 5, session),
+                game_world: None,
+                swerve: false,
+            }
+        }
+
+        /// Sends `input` for the local snake and steps every tick before
+        /// `until` that both players' moves have arrived for.
+        fn frame(&mut self, input: &Input, until: u32) {
+            self.lockstep.poll().unwrap();
+            self.lockstep.push_local(input);
+            if self.game_world.is_none() && self.lockstep.connected() {
+                let mut game_world = GameWorld::new(self.lockstep.seed().unwrap());
+                game_world.players = 2;
+                game_world.set_mode(GameMode::Zen);
+                systems::create_arena_system(&mut game_world);
+                systems::create_snake_system(&mut game_world);
+                self.game_world = Some(game_world);
+            }
+            if let Some(game_world) = &mut self.game_world {
+                while self.lockstep.tick() < until {
+                    let Some(mut input) = self.lockstep.next_input() else {
+                        break;
+                    };
+                    let tick = self.lockstep.tick() - 1;
+                    if self.swerve && (10..70).contains(&tick) {
+                        let direction = Direction::ALL[(tick / 4 % 4) as usize];
+                        input.press_move(0, direction);
+                        input.press_move(1, direction);
+                    }
+                    game_world.events.clear();
+                    systems::update_input(game_world, &input);
+                    systems::step_system(game_world, STEP);
+                    systems::despawn_food_system(game_world);
+                    self.lockstep.record_hash(state_hash(game_world));
+                }
+            }
+            self.lockstep.send().unwrap();
+        }
+    }
+
+    /// A host and a guest on either end of a link that loses a fifth of the
+    /// packets and holds the rest back for a few milliseconds.
+    fn lossy_peers() -> (Peer, Peer) {
+        let to_host = Queue::default();
+        let to_guest = Queue::default();
+        let host = Pipe {
+            outgoing: to_guest.clone(),
+            incoming: to_host.clone(),
+        };
+        let guest = Pipe {
+            outgoing: to_host,
+            incoming: to_guest,
+        };
+        (
+            Peer::new(LossyTransport::new(host, 0.2, 0.002, 0.004, 1), 0),
+            Peer::new(LossyTransport::new(guest, 0.2, 0.002, 0.004, 2), 1),
+        )
+    }
+
+    /// Plays frames a millisecond apart, pressing moves at random on both
+    /// ends, until both peers have stepped exactly `ticks` ticks. A few more frames
+    /// follow so the hashes of the last ticks reach the other end.
+    fn play(host: &mut Peer, guest: &mut Peer, ticks: u32) {
+        let mut rng = Rng::new(9);
+        let mut frames = 0;
+        while host.lockstep.tick() < ticks || guest.lockstep.tick() < ticks {
+            frames += 1;
+            assert!(
+                frames < 20_000,
+                "stuck at ticks {} and {}",
+                host.lockstep.tick(),
+                guest.lockstep.tick()
+            );
+            for peer in [&mut *host, &mut *guest] {
+                let mut input = Input::default();
+                if rng.gen_range(0, 20) == 0 {
+                    input.press_move(0, Direction::ALL[rng.gen_range(0, 4) as usize]);
+                }
+                peer.frame(&input, ticks);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        for _ in 0..50 {
+            for peer in [&mut *host, &mut *guest] {
+                peer.lockstep.poll().unwrap();
+                peer.lockstep.send().unwrap();
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn peers_stay_in_step_over_a_bad_link() {
+        let (mut host, mut guest) = lossy_peers();
+        play(&mut host, &mut guest, 300);
+        assert_eq!(host.lockstep.desync(), None);
+        assert_eq!(guest.lockstep.desync(), None);
+
+        assert_eq!(host.lockstep.tick(), 300);
+        assert_eq!(guest.lockstep.tick(), 300);
+        let host_world = host.game_world.as_ref().unwrap();
+        let guest_world = guest.game_world.as_ref().unwrap();
+        assert_eq!(state_hash(host_world), state_hash(guest_world));
+    }
+
+    #[test]
+    fn a_peer_playing_other_moves_is_caught() {
+        let (mut host, mut guest) = lossy_peers();
+        guest.swerve = true;
+        play(&mut host, &mut guest, 200);
+        let desync = host.lockstep.desync().unwrap();
+        assert_eq!(guest.lockstep.desync().map(|d| d.tick), Some(desync.tick));
+        assert_ne!(desync.local, desync.remote);
+        assert!((30..=90).contains(&desync.tick));
+    }
+}
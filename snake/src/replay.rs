@@ -0,0 +1,374 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::arena::{Arena, WallMode};
+use crate::components::{Action, Input};
+use crate::level::{self, Level};
+use crate::mode::GameMode;
+
+/// Bumped whenever the layout of a replay file, or how one plays back,
+/// changes.
+pub const REPLAY_VERSION: u32 = 1;
+
+const MAGIC: &str = "snek-replay";
+const TICKS_PER_LINE: usize = 64;
+
+const LEFT: u8 = 0b0001;
+const RIGHT: u8 = 0b0010;
+const UP: u8 = 0b0100;
+const DOWN: u8 = 0b1000;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    MissingHeader,
+    UnsupportedVersion(u32),
+    Malformed { line: usize },
+    TickCountMismatch { expected: usize, found: usize },
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(err: io::Error) -> Self {
+        ReplayError::Io(err)
+    }
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => write!(f, "{}", err),
+            ReplayError::MissingHeader => write!(f, "not a replay file"),
+            ReplayError::UnsupportedVersion(v) => write!(f, "unsupported replay version {}", v),
+            ReplayError::Malformed { line } => write!(f, "malformed replay on line {}", line),
+            ReplayError::TickCountMismatch { expected, found } => {
+                write!(f, "replay claims {} ticks but has {}", expected, found)
+            }
+        }
+    }
+}
+
+/// The seed, arena, players, mode and level a run started from and the
+/// `Input` fed to every simulation tick. Only the movement actions affect the
+/// simulation, so those are all we keep, one hex digit per player per tick.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub seed: u64,
+    pub arena: Arena,
+    pub players: u8,
+    pub mode: GameMode,
+    /// Kept whole, so the replay plays the same whatever happens to the
+    /// level file.
+    pub level: Option<Level>,
+    pub inputs: Vec<Input>,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self::new(0, Arena::default(), 1, GameMode::Classic)
+    }
+}
+
+impl Replay {
+    pub fn new(seed: u64, arena: Arena, players: u8, mode: GameMode) -> Self {
+        Self {
+            seed,
+            arena,
+            players,
+            mode,
+            level: None,
+            inputs: Vec::with_capacity(1024),
+        }
+    }
+
+    pub fn record(&mut self, input: &Input) {
+        let mut kept = Input::default();
+        for player in 0..self.players {
+            decode(encode(input, player), player, &mut kept);
+        }
+        self.inputs.push(kept);
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ReplayError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ReplayError> {
+        let contents = fs::read_to_string(path)?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, ReplayError> {
+        let mut lines = contents.lines().enumerate();
+
+        let version = match lines
+            .next()
+            .map(|(_, l)| l.split_whitespace().collect::<Vec<_>>())
+        {
+            Some(header) if header.len() == 2 && header[0] == MAGIC => header[1]
+                .parse::<u32>()
+                .map_err(|_| ReplayError::MissingHeader)?,
+            _ => return Err(ReplayError::MissingHeader),
+        };
+        if version != REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+
+        let seed = parse_field(lines.next(), "seed")?;
+        let arena = parse_arena(lines.next())?;
+        let players = match parse_field(lines.next(), "players")? {
+            players if (1..=Action::MAX_PLAYERS as u64).contains(&players) => players as u8,
+            _ => return Err(ReplayError::Malformed { line: 4 }),
+        };
+        let mode = parse_mode(lines.next())?;
+        let level = parse_level(&mut lines)?;
+        let ticks = parse_field(lines.next(), "ticks")? as usize;
+
+        let mut replay = Replay::new(seed, arena, players, mode);
+        replay.level = level;
+        let mut input = Input::default();
+        let mut player = 0;
+        for (index, line) in lines {
+            for c in line.trim().chars() {
+                let bits = c
+                    .to_digit(16)
+                    .ok_or(ReplayError::Malformed { line: index + 1 })?;
+                decode(bits as u8, player, &mut input);
+                player += 1;
+                if player == players {
+                    replay.inputs.push(std::mem::take(&mut input));
+                    player = 0;
+                }
+            }
+        }
+        if player != 0 {
+            return Err(ReplayError::TickCountMismatch {
+                expected: ticks,
+                found: replay.inputs.len() + 1,
+            });
+        }
+        if replay.inputs.len() != ticks {
+            return Err(ReplayError::TickCountMismatch {
+                expected: ticks,
+                found: replay.inputs.len(),
+            });
+        }
+        Ok(replay)
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", MAGIC, REPLAY_VERSION)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(
+            f,
+            "arena {} {} {}",
+            self.arena.width,
+            self.arena.height,
+            self.arena.walls.name()
+        )?;
+        writeln!(f, "players {}", self.players)?;
+        writeln!(f, "mode {}", self.mode.name())?;
+        level::write_embedded(f, self.level.as_ref())?;
+        writeln!(f, "ticks {}", self.inputs.len())?;
+        for chunk in self.inputs.chunks(TICKS_PER_LINE) {
+            for input in chunk {
+                for player in 0..self.players {
+                    write!(f, "{:x}", encode(input, player))?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hands the recorded inputs back out, one per tick.
+#[derive(Debug, Clone)]
+pub struct Playback {
+    replay: Replay,
+    cursor: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Self { replay, cursor: 0 }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.replay.seed
+    }
+
+    pub fn arena(&self) -> Arena {
+        self.replay.arena
+    }
+
+    pub fn players(&self) -> u8 {
+        self.replay.players
+    }
+
+    pub fn mode(&self) -> GameMode {
+        self.replay.mode
+    }
+
+    pub fn level(&self) -> Option<&Level> {
+        self.replay.level.as_ref()
+    }
+
+    pub fn finished(&self) -> bool {
+        self.cursor >= self.replay.inputs.len()
+    }
+
+    pub fn next_input(&mut self) -> Option<Input> {
+        let input = self.replay.inputs.get(self.cursor).copied();
+        if input.is_some() {
+            self.cursor += 1;
+        }
+        input
+    }
+}
+
+fn parse_field(line: Option<(usize, &str)>, name: &str) -> Result<u64, ReplayError> {
+    let (index, line) = line.ok_or(ReplayError::MissingHeader)?;
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next().map(str::parse::<u64>)) {
+        (Some(key), Some(Ok(value))) if key == name => Ok(value),
+        _ => Err(ReplayError::Malformed { line: index + 1 }),
+    }
+}
+
+fn parse_mode(line: Option<(usize, &str)>) -> Result<GameMode, ReplayError> {
+    let (index, line) = line.ok_or(ReplayError::MissingHeader)?;
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next().and_then(GameMode::from_name)) {
+        (Some("mode"), Some(mode)) => Ok(mode),
+        _ => Err(ReplayError::Malformed { line: index + 1 }),
+    }
+}
+
+fn parse_level<'a, I>(lines: &mut I) -> Result<Option<Level>, ReplayError>
+where
+    I: Iterator<Item = (usize, &'a str)>,
+{
+    let (index, line) = lines.next().ok_or(ReplayError::MissingHeader)?;
+    let malformed = ReplayError::Malformed { line: index + 1 };
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("level"), Some(count), None) => level::read_embedded(count, lines).ok_or(malformed),
+        _ => Err(malformed),
+    }
+}
+
+fn parse_arena(line: Option<(usize, &str)>) -> Result<Arena, ReplayError> {
+    let (index, line) = line.ok_or(ReplayError::MissingHeader)?;
+    let malformed = ReplayError::Malformed { line: index + 1 };
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() != 4 || parts[0] != "arena" {
+        return Err(malformed);
+    }
+    let (width, height) = match (parts[1].parse::<i32>(), parts[2].parse::<i32>()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => (width, height),
+        _ => return Err(malformed),
+    };
+    let walls = match WallMode::from_name(parts[3]) {
+        Some(walls) => walls,
+        _ => return Err(malformed),
+    };
+    Ok(Arena::new(width, height, walls))
+}
+
+const BITS: [u8; 4] = [LEFT, RIGHT, UP, DOWN];
+
+/// The moves of `player`, in the order of `Action::MOVES`, paired with the
+/// bits they are written as. Players without moves of their own have none.
+fn moves(player: u8) -> impl Iterator<Item = (Action, u8)> {
+    Action::MOVES
+        .get(player as usize)
+        .into_iter()
+        .flat_map(|moves| moves.iter().copied().zip(BITS.iter().copied()))
+}
+
+pub(crate) fn encode(input: &Input, player: u8) -> u8 {
+    moves(player)
+        .filter(|(action, _)| input.pressed(*action))
+        .fold(0, |bits, (_, bit)| bits | bit)
+}
+
+pub(crate) fn decode(bits: u8, player: u8, input: &mut Input) {
+    for (action, bit) in moves(player) {
+        if bits & bit != 0 {
+            input.press(action);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::STEP;
+    use crate::components::Direction;
+    use crate::net;
+    use crate::rng::Rng;
+    use crate::systems::{self, GameWorld};
+
+    /// Plays `inputs` from the start of a run and hashes where it ended up,
+    /// with how many ticks it took.
+    fn play(replay: &Replay, inputs: impl Iterator<Item = Input>) -> (u64, usize) {
+        let mut game_world = GameWorld::new(replay.seed);
+        game_world.arena = replay.arena;
+        game_world.players = replay.players;
+        game_world.set_mode(replay.mode);
+        systems::create_arena_system(&mut game_world);
+        systems::create_snake_system(&mut game_world);
+        let mut scores = vec![0; replay.players as usize];
+        let mut ticks = 0;
+        for input in inputs {
+            game_world.events.clear();
+            systems::update_input(&mut game_world, &input);
+            systems::step_system(&mut game_world, STEP);
+            systems::despawn_food_system(&mut game_world);
+            systems::update_score_system(&mut game_world, &mut scores);
+            systems::speed_system(&mut game_world, &scores);
+            ticks += 1;
+            if systems::game_over_system(&game_world).is_some() {
+                break;
+            }
+        }
+        (net::state_hash(&game_world), ticks)
+    }
+
+    #[test]
+    fn a_saved_replay_plays_back_the_same() {
+        let mut replay = Replay::new(7, Arena::default(), 2, GameMode::Zen);
+        let mut rng = Rng::new(3);
+        for _ in 0..1200 {
+            let mut input = Input::default();
+            for player in 0..replay.players {
+                if rng.gen_range(0, 10) == 0 {
+                    let direction = Direction::ALL[rng.gen_range(0, 4) as usize];
+                    input.press_move(player, direction);
+                }
+            }
+            replay.record(&input);
+        }
+        let recorded = play(&replay, replay.inputs.iter().copied());
+
+        let loaded = Replay::parse(&replay.to_string()).unwrap();
+        assert_eq!(loaded.to_string(), replay.to_string());
+        let mut playback = Playback::new(loaded.clone());
+        let played = play(&loaded, std::iter::from_fn(|| playback.next_input()));
+        assert_eq!(played, recorded);
+        assert!(playback.finished());
+    }
+}
@@ -0,0 +1,42 @@
+/// Saved position of an `Rng`. Restoring it replays the exact same draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RngState(pub u64);
+
+/// Small splitmix64 generator. Every bit of randomness in the simulation is
+/// drawn from the one owned by `GameWorld`, so a seed and a list of inputs are
+/// enough to reproduce a game.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `low..high`. Returns `low` if the range is empty.
+    pub fn gen_range(&mut self, low: i32, high: i32) -> i32 {
+        if high <= low {
+            return low;
+        }
+        let span = (high as i64 - low as i64) as u64;
+        (low as i64 + (self.next_u64() % span) as i64) as i32
+    }
+
+    pub fn snapshot(&self) -> RngState {
+        RngState(self.state)
+    }
+
+    pub fn restore(&mut self, state: RngState) {
+        self.state = state.0;
+    }
+}
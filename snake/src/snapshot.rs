@@ -0,0 +1,612 @@
+// TODO(jhurstwright): Replace with no_std hashmap
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::arena::{Arena, WallMode};
+use crate::components::{self, Direction, GridPos, Player, Timer};
+use crate::food::{FoodKind, SlowMotion};
+use crate::level::{self, Level};
+use crate::mode::GameMode;
+use crate::rng::RngState;
+use crate::systems::{self, GameWorld};
+
+/// Bumped whenever the layout of a save file changes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+const MAGIC: &str = "snek-save";
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    MissingHeader,
+    UnsupportedVersion(u32),
+    Malformed { line: usize },
+    MissingHead,
+    BrokenTail { segment: usize },
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "{}", err),
+            SnapshotError::MissingHeader => write!(f, "not a save file"),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported save version {}", v),
+            SnapshotError::Malformed { line } => write!(f, "malformed save on line {}", line),
+            SnapshotError::MissingHead => write!(f, "save has no snake head"),
+            SnapshotError::BrokenTail { segment } => {
+                write!(f, "tail segment {} follows nothing", segment)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeadSnapshot {
+    pub player: Player,
+    pub pos: GridPos,
+    pub velocity: Direction,
+    pub direction: Direction,
+    pub dead: bool,
+}
+
+/// A tail segment. `ahead` is 0 for its player's head, otherwise the 1 based
+/// index into `Snapshot::tail` of the segment it follows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TailSnapshot {
+    pub player: Player,
+    pub segment: usize,
+    pub pos: GridPos,
+    pub ahead: usize,
+}
+
+/// A piece of food.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FoodSnapshot {
+    pub pos: GridPos,
+    pub kind: FoodKind,
+    /// How far through its lifetime it was, for food that doesn't stay for
+    /// good. Food without one starts its lifetime over when loaded.
+    pub lifetime: Option<f64>,
+}
+
+/// Everything needed to pick a game back up where it was left. Entities are
+/// written as plain data and get new ids when they are spawned again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub arena: Arena,
+    pub mode: GameMode,
+    /// The level being played, kept whole like it is in replays.
+    pub level: Option<Level>,
+    pub rng: RngState,
+    /// Indexed by player.
+    pub scores: Vec<i32>,
+    /// How far through their durations the move and food timers were.
+    pub move_timer: f64,
+    pub food_timer: f64,
+    /// Seconds the run had lasted.
+    pub elapsed: f64,
+    pub heads: Vec<HeadSnapshot>,
+    pub tail: Vec<TailSnapshot>,
+    pub food: Vec<FoodSnapshot>,
+    pub slow_motion: Option<SlowMotion>,
+}
+
+impl Snapshot {
+    /// `scores` is indexed by player.
+    pub fn capture(game_world: &GameWorld, scores: &[i32]) -> Result<Self, SnapshotError> {
+        let GameWorld { world, .. } = game_world;
+        let mut heads: Vec<(hecs::Entity, HeadSnapshot)> = world
+            .query::<(
+                &components::Snake,
+                &Player,
+                &GridPos,
+                &components::HeadDirection,
+                Option<&components::Velocity>,
+                Option<&components::Dead>,
+            )>()
+            .iter()
+            .map(|(entity, (_, player, pos, direction, velocity, dead))| {
+                let head = HeadSnapshot {
+                    player: *player,
+                    pos: *pos,
+                    // Dead snakes lose their velocity, which way they were
+                    // facing is as good as any.
+                    velocity: velocity.map(|v| v.0).unwrap_or(direction.0),
+                    direction: direction.0,
+                    dead: dead.is_some(),
+                };
+                (entity, head)
+            })
+            .collect();
+        if heads.is_empty() {
+            return Err(SnapshotError::MissingHead);
+        }
+        heads.sort_by_key(|(_, head)| head.player.0);
+
+        let mut tails: Vec<(hecs::Entity, Player, usize, GridPos, hecs::Entity)> = world
+            .query::<(&components::Tail, &Player, &GridPos)>()
+            .iter()
+            .map(|(entity, (tail, player, pos))| (entity, *player, tail.segment, *pos, tail.ahead))
+            .collect();
+        tails.sort_by_key(|(_, player, segment, _, _)| (player.0, *segment));
+
+        let mut ids: HashMap<hecs::Entity, usize> = HashMap::new();
+        for (entity, _) in heads.iter() {
+            ids.insert(*entity, 0);
+        }
+        for (index, (entity, _, _, _, _)) in tails.iter().enumerate() {
+            ids.insert(*entity, index + 1);
+        }
+        let tail = tails
+            .iter()
+            .map(|(_, player, segment, pos, ahead)| {
+                let ahead = *ids
+                    .get(ahead)
+                    .ok_or(SnapshotError::BrokenTail { segment: *segment })?;
+                Ok(TailSnapshot {
+                    player: *player,
+                    segment: *segment,
+                    pos: *pos,
+                    ahead,
+                })
+            })
+            .collect::<Result<Vec<_>, SnapshotError>>()?;
+
+        let food = world
+            .query::<(&components::Food, &GridPos, Option<&components::Lifetime>)>()
+            .iter()
+            .map(|(_, (food, pos, lifetime))| FoodSnapshot {
+                pos: *pos,
+                kind: food.0,
+                lifetime: lifetime.map(|lifetime| lifetime.0.alpha()),
+            })
+            .collect();
+
+        Ok(Self {
+            arena: game_world.arena,
+            mode: game_world.mode,
+            level: game_world.level.clone(),
+            rng: game_world.rng.snapshot(),
+            scores: scores.to_vec(),
+            move_timer: game_world.move_timer.alpha(),
+            food_timer: game_world.food_timer.alpha(),
+            elapsed: game_world.elapsed,
+            heads: heads.into_iter().map(|(_, head)| head).collect(),
+            tail,
+            food,
+            slow_motion: game_world.slow_motion.clone(),
+        })
+    }
+
+    /// Replaces the simulation in `game_world` with this snapshot and hands
+    /// back the saved scores.
+    pub fn restore(&self, game_world: &mut GameWorld) -> Vec<i32> {
+        systems::clear_simulation_system(game_world);
+        game_world.events.clear();
+        game_world.level = self.level.clone();
+        game_world.arena = self.arena;
+        game_world.set_mode(self.mode);
+        game_world.rng.restore(self.rng);
+        game_world.elapsed = self.elapsed;
+        game_world.slow_motion = self.slow_motion.clone();
+        game_world.players = self.heads.len() as u8;
+        systems::create_arena_system(game_world);
+
+        let GameWorld {
+            world, grid, rules, ..
+        } = game_world;
+        let mut heads: HashMap<Player, hecs::Entity> = HashMap::new();
+        for head in self.heads.iter() {
+            let entity =
+                systems::spawn_snake_head(world, grid, head.player, head.pos, head.velocity);
+            // Only the last queued turn is saved, which is enough to keep
+            // going the way the player was heading.
+            if let Ok(mut turns) = world.get_mut::<components::TurnQueue>(entity) {
+                turns.push(head.direction, head.velocity);
+            }
+            if let Ok(mut direction) = world.get_mut::<components::HeadDirection>(entity) {
+                direction.0 = head.direction;
+            }
+            heads.insert(head.player, entity);
+        }
+
+        // Tails are spawned pointing at their head and pointed at the
+        // segment they really follow once every segment exists.
+        let mut entities = Vec::with_capacity(self.tail.len());
+        for tail in self.tail.iter() {
+            let ahead = heads[&tail.player];
+            let entity = systems::spawn_snake_tail(
+                world,
+                grid,
+                tail.player,
+                tail.pos,
+                components::Tail {
+                    segment: tail.segment,
+                    ahead,
+                },
+            );
+            entities.push(entity);
+        }
+        for (tail, entity) in self.tail.iter().zip(entities.iter()) {
+            if tail.ahead == 0 {
+                continue;
+            }
+            let ahead = entities[tail.ahead - 1];
+            if let Ok(mut segment) = world.get_mut::<components::Tail>(*entity) {
+                segment.ahead = ahead;
+            }
+        }
+
+        for head in self.heads.iter().filter(|head| head.dead) {
+            systems::kill_snake(world, heads[&head.player], head.player);
+        }
+
+        for food in self.food.iter() {
+            let lifetime = rules.food_lifetime(food.kind);
+            let entity = systems::spawn_food(world, grid, food.pos, food.kind, lifetime);
+            if let (Some(alpha), Ok(mut lifetime)) =
+                (food.lifetime, world.get_mut::<components::Lifetime>(entity))
+            {
+                lifetime.0.set_alpha(alpha);
+            }
+        }
+
+        // The pace depends on how long the snakes are, so the move timer
+        // can only be wound forward once they are back.
+        systems::speed_system(game_world, &self.scores);
+        game_world.move_timer.set_alpha(self.move_timer);
+        game_world.food_timer.set_alpha(self.food_timer);
+        self.scores.clone()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        let contents = fs::read_to_string(path)?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, SnapshotError> {
+        let mut lines = contents.lines().enumerate();
+        let version = match lines
+            .next()
+            .map(|(_, l)| l.split_whitespace().collect::<Vec<_>>())
+        {
+            Some(header) if header.len() == 2 && header[0] == MAGIC => header[1]
+                .parse::<u32>()
+                .map_err(|_| SnapshotError::MissingHeader)?,
+            _ => return Err(SnapshotError::MissingHeader),
+        };
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let mut arena = Arena::default();
+        let mut mode = GameMode::Classic;
+        let mut level = None;
+        let mut rng = RngState(0);
+        let mut scores = Vec::new();
+        let mut timers = (0., 0.);
+        let mut elapsed = 0.;
+        let mut heads = Vec::new();
+        let mut tail = Vec::new();
+        let mut food = Vec::new();
+        let mut slow_motion = None;
+        while let Some((index, line)) = lines.next() {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            // Score, head and tail lines start with the player they belong
+            // to.
+            let (player, parts) = match parts.as_slice() {
+                [kind @ "score", player, rest @ ..]
+                | [kind @ "head", player, rest @ ..]
+                | [kind @ "tail", player, rest @ ..] => {
+                    let player = player
+                        .parse()
+                        .map_err(|_| SnapshotError::Malformed { line: index + 1 })?;
+                    let player = Player(player);
+                    let mut parts = vec![*kind];
+                    parts.extend_from_slice(rest);
+                    (player, parts)
+                }
+                _ => (Player(0), parts.to_vec()),
+            };
+            let malformed = SnapshotError::Malformed { line: index + 1 };
+            match parts.as_slice() {
+                [] => {}
+                ["arena", width, height, walls] => {
+                    arena = parse_arena(width, height, walls).ok_or(malformed)?;
+                }
+                ["mode", name] => {
+                    mode = GameMode::from_name(name).ok_or(malformed)?;
+                }
+                // The level file follows on the lines after this one.
+                ["level", count] => {
+                    level = level::read_embedded(count, &mut lines).ok_or(malformed)?;
+                }
+                ["rng", state] => {
+                    rng = RngState(state.parse().map_err(|_| malformed)?);
+                }
+                ["score", value] => {
+                    let value = value.parse().map_err(|_| malformed)?;
+                    let index = player.0 as usize;
+                    if scores.len() <= index {
+                        scores.resize(index + 1, 0);
+                    }
+                    scores[index] = value;
+                }
+                ["timers", move_timer, food_timer] => {
+                    timers = match (move_timer.parse(), food_timer.parse()) {
+                        (Ok(m), Ok(f)) => (m, f),
+                        _ => return Err(malformed),
+                    };
+                }
+                ["elapsed", value] => {
+                    elapsed = value.parse().map_err(|_| malformed)?;
+                }
+                ["head", x, y, velocity, direction, rest @ ..] => {
+                    let dead = match rest {
+                        [] => false,
+                        ["dead"] => true,
+                        _ => return Err(malformed),
+                    };
+                    heads.push(
+                        match (
+                            parse_pos(x, y),
+                            Direction::from_name(velocity),
+                            Direction::from_name(direction),
+                        ) {
+                            (Some(pos), Some(velocity), Some(direction)) => HeadSnapshot {
+                                player,
+                                pos,
+                                velocity,
+                                direction,
+                                dead,
+                            },
+                            _ => return Err(malformed),
+                        },
+                    );
+                }
+                ["tail", segment, x, y, ahead] => {
+                    match (segment.parse(), parse_pos(x, y), ahead.parse()) {
+                        (Ok(segment), Some(pos), Ok(ahead)) => tail.push(TailSnapshot {
+                            player,
+                            segment,
+                            pos,
+                            ahead,
+                        }),
+                        _ => return Err(malformed),
+                    }
+                }
+                ["food", x, y, rest @ ..] => {
+                    let parsed = match rest {
+                        [kind] => FoodKind::from_name(kind).map(|kind| (kind, None)),
+                        [kind, alpha] => match (FoodKind::from_name(kind), alpha.parse()) {
+                            (Some(kind), Ok(alpha)) => Some((kind, Some(alpha))),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    match (parse_pos(x, y), parsed) {
+                        (Some(pos), Some((kind, lifetime))) => food.push(FoodSnapshot {
+                            pos,
+                            kind,
+                            lifetime,
+                        }),
+                        _ => return Err(malformed),
+                    }
+                }
+                ["slow", factor, seconds, alpha] => {
+                    slow_motion = match (factor.parse(), seconds.parse(), alpha.parse()) {
+                        (Ok(factor), Ok(seconds), Ok(alpha)) => {
+                            let mut timer = Timer::new(seconds);
+                            timer.set_alpha(alpha);
+                            Some(SlowMotion { factor, timer })
+                        }
+                        _ => return Err(malformed),
+                    };
+                }
+                _ => return Err(malformed),
+            }
+        }
+
+        if heads.is_empty() {
+            return Err(SnapshotError::MissingHead);
+        }
+        let broken = tail.iter().find(|t| {
+            t.ahead > tail.len()
+                || !heads.iter().any(|head| head.player == t.player)
+                || (t.ahead > 0 && tail[t.ahead - 1].player != t.player)
+        });
+        if let Some(broken) = broken {
+            return Err(SnapshotError::BrokenTail {
+                segment: broken.segment,
+            });
+        }
+        Ok(Self {
+            arena,
+            mode,
+            level,
+            rng,
+            scores,
+            move_timer: timers.0,
+            food_timer: timers.1,
+            elapsed,
+            heads,
+            tail,
+            food,
+            slow_motion,
+        })
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", MAGIC, SNAPSHOT_VERSION)?;
+        writeln!(
+            f,
+            "arena {} {} {}",
+            self.arena.width,
+            self.arena.height,
+            self.arena.walls.name()
+        )?;
+        writeln!(f, "mode {}", self.mode.name())?;
+        level::write_embedded(f, self.level.as_ref())?;
+        writeln!(f, "rng {}", self.rng.0)?;
+        for (player, score) in self.scores.iter().enumerate() {
+            writeln!(f, "score {} {}", player, score)?;
+        }
+        writeln!(f, "timers {} {}", self.move_timer, self.food_timer)?;
+        writeln!(f, "elapsed {}", self.elapsed)?;
+        for head in self.heads.iter() {
+            write!(
+                f,
+                "head {} {} {} {} {}",
+                head.player.0,
+                head.pos.0,
+                head.pos.1,
+                head.velocity.name(),
+                head.direction.name()
+            )?;
+            if head.dead {
+                write!(f, " dead")?;
+            }
+            writeln!(f)?;
+        }
+        for tail in self.tail.iter() {
+            writeln!(
+                f,
+                "tail {} {} {} {} {}",
+                tail.player.0, tail.segment, tail.pos.0, tail.pos.1, tail.ahead
+            )?;
+        }
+        for food in self.food.iter() {
+            write!(f, "food {} {} {}", food.pos.0, food.pos.1, food.kind.name())?;
+            if let Some(alpha) = food.lifetime {
+                write!(f, " {}", alpha)?;
+            }
+            writeln!(f)?;
+        }
+        if let Some(slow_motion) = &self.slow_motion {
+            writeln!(
+                f,
+                "slow {} {} {}",
+                slow_motion.factor,
+                slow_motion.timer.duration(),
+                slow_motion.timer.alpha()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_pos(x: &str, y: &str) -> Option<GridPos> {
+    match (x.parse(), y.parse()) {
+        (Ok(x), Ok(y)) => Some(GridPos(x, y)),
+        _ => None,
+    }
+}
+
+fn parse_arena(width: &str, height: &str, walls: &str) -> Option<Arena> {
+    match (width.parse(), height.parse(), WallMode::from_name(walls)) {
+        (Ok(width), Ok(height), Some(walls)) if width > 0 && height > 0 => {
+            Some(Arena::new(width, height, walls))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::Tail;
+
+    /// A two player run whose snakes have grown a few segments each, so the
+    /// tail of the second player is indexed past the first's.
+    fn grown_world() -> GameWorld {
+        let mut game_world = GameWorld::new(5);
+        game_world.players = 2;
+        game_world.set_mode(GameMode::TimeAttack);
+        systems::create_arena_system(&mut game_world);
+        systems::create_snake_system(&mut game_world);
+        for player in [Player(0), Player(1)] {
+            for _ in 0..3 {
+                let (last, tail, pos) = game_world
+                    .world
+                    .query::<(&Tail, &Player, &GridPos)>()
+                    .iter()
+                    .filter(|(_, (_, owner, _))| **owner == player)
+                    .map(|(entity, (tail, _, pos))| (entity, tail.segment, *pos))
+                    .max_by_key(|(_, segment, _)| *segment)
+                    .unwrap();
+                let GameWorld { world, grid, .. } = &mut game_world;
+                let tail = Tail {
+                    segment: tail + 1,
+                    ahead: last,
+                };
+                systems::spawn_snake_tail(world, grid, player, pos.offset(Direction::Down), tail);
+            }
+        }
+        for _ in 0..100 {
+            systems::step_system(&mut game_world, crate::clock::STEP);
+        }
+        game_world
+    }
+
+    #[test]
+    fn a_save_loads_and_saves_back_the_same() {
+        let game_world = grown_world();
+        let snapshot = Snapshot::capture(&game_world, &[3, 1]).unwrap();
+        assert_eq!(snapshot.tail.len(), 8);
+        assert!(!snapshot.food.is_empty());
+        let text = snapshot.to_string();
+
+        let loaded = Snapshot::parse(&text).unwrap();
+        assert_eq!(loaded, snapshot);
+        let mut restored = GameWorld::new(0);
+        let scores = loaded.restore(&mut restored);
+        assert_eq!(scores, vec![3, 1]);
+        let again = Snapshot::capture(&restored, &scores).unwrap();
+        assert_eq!(again.to_string(), text);
+    }
+
+    #[test]
+    fn restored_tails_follow_the_segment_they_followed() {
+        let mut game_world = grown_world();
+        let snapshot = Snapshot::capture(&game_world, &[0, 0]).unwrap();
+        let mut restored = GameWorld::new(0);
+        snapshot.restore(&mut restored);
+
+        for _ in 0..30 {
+            systems::step_system(&mut game_world, crate::clock::STEP);
+            systems::step_system(&mut restored, crate::clock::STEP);
+        }
+        let moved = Snapshot::capture(&game_world, &[0, 0]).unwrap();
+        let restored = Snapshot::capture(&restored, &[0, 0]).unwrap();
+        assert_eq!(restored.heads, moved.heads);
+        assert_eq!(restored.tail, moved.tail);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let text = Snapshot::capture(&grown_world(), &[0, 0])
+            .unwrap()
+            .to_string()
+            .replacen("snek-save 1", "snek-save 2", 1);
+        match Snapshot::parse(&text) {
+            Err(SnapshotError::UnsupportedVersion(2)) => {}
+            other => panic!("expected an unsupported version, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,1230 @@
+// TODO(jhurstwright): Replace with no_std hashmap
+use std::collections::HashMap;
+
+use crate::ai::SnakeController;
+use crate::arena::{Arena, WallMode};
+use crate::components;
+use crate::components::GridPos;
+use crate::events;
+use crate::events::{DeathCause, Event};
+use crate::food::{self, FoodEffect, FoodKind, SlowMotion};
+use crate::grid::SpatialGrid;
+use crate::level::{self, Level};
+use crate::mode::{GameMode, Rules, SpeedBasis};
+use crate::rng::Rng;
+
+pub struct GameWorld {
+    pub world: hecs::World,
+    pub events: Vec<events::Event>,
+    pub move_timer: components::Timer,
+    pub food_timer: components::Timer,
+    pub rng: Rng,
+    pub arena: Arena,
+    pub grid: SpatialGrid,
+    /// Seconds simulated since the current run started.
+    pub elapsed: f64,
+    /// Goes up every time the board is cleared, so anything that remembers
+    /// a run can tell when it is over.
+    pub run: u64,
+    /// How many snakes a run starts with.
+    pub players: u8,
+    pub mode: GameMode,
+    /// The rules of `mode`, unless they were changed by hand.
+    pub rules: Rules,
+    /// Set while moves are slowed down by something a snake ate.
+    pub slow_motion: Option<SlowMotion>,
+    /// Walls, spawns and food zones laid out by hand. `None` is an empty
+    /// arena.
+    pub level: Option<Level>,
+}
+
+impl GameWorld {
+    /// Creates an empty world whose randomness is drawn from `seed`, so the
+    /// same seed and the same inputs always play out the same way.
+    pub fn new(seed: u64) -> Self {
+        let rules = Rules::default();
+        Self {
+            events: Vec::with_capacity(32),
+            world: hecs::World::new(),
+            move_timer: components::Timer::new(rules.move_time),
+            food_timer: components::Timer::new(rules.food_time),
+            rng: Rng::new(seed),
+            arena: Arena::default(),
+            grid: SpatialGrid::new(),
+            elapsed: 0.,
+            run: 0,
+            players: 1,
+            mode: GameMode::Classic,
+            rules,
+            slow_motion: None,
+            level: None,
+        }
+    }
+
+    /// Plays on `level`, in its arena.
+    pub fn set_level(&mut self, level: Level) {
+        self.arena = level.arena;
+        self.level = Some(level);
+    }
+
+    /// Plays by `mode`'s rules.
+    pub fn set_mode(&mut self, mode: GameMode) {
+        self.mode = mode;
+        self.rules = mode.rules();
+        self.apply_rules();
+    }
+
+    /// Restarts the timers at the pace `rules` sets. Needed after changing
+    /// `rules` by hand.
+    pub fn apply_rules(&mut self) {
+        self.move_timer = components::Timer::new(self.rules.move_time);
+        self.food_timer = components::Timer::new(self.rules.food_time);
+    }
+
+    /// The arena as it is played. `arena` is left as it was set up, but
+    /// where crashing doesn't kill its edges wrap around.
+    pub fn play_arena(&self) -> Arena {
+        self.rules.play_arena(self.arena)
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+}
+
+/// Runs a single fixed step of `dt` seconds of the game rules.
+pub fn step_system(game_world: &mut GameWorld, dt: f64) {
+    game_world.elapsed += dt;
+    game_world.move_timer.tick(dt);
+    game_world.food_timer.tick(dt);
+    expire_food_system(game_world, dt);
+
+    if game_world.move_timer.finished() {
+        store_previous_position_system(game_world);
+        update_velocity_direction(game_world);
+        tail_movement_system(game_world);
+        head_collision_system(game_world);
+        handle_collision_system(game_world);
+        food_effect_system(game_world);
+        death_system(game_world);
+        trim_tail_system(game_world);
+        trigger_tail_spawn(game_world);
+        movement_system(game_world);
+        spawn_tail_system(game_world);
+        game_world.move_timer.reset();
+    }
+    if game_world.food_timer.finished() {
+        add_food_system(game_world);
+        game_world.food_timer.reset();
+    }
+    slow_motion_system(game_world, dt);
+    time_limit_system(game_world, dt);
+    sync_position_system(game_world);
+}
+
+/// Runs down the current slow motion and starts any a snake ate this tick,
+/// which replaces it.
+pub fn slow_motion_system(game_world: &mut GameWorld, dt: f64) {
+    let over = match &mut game_world.slow_motion {
+        Some(slow_motion) => {
+            slow_motion.timer.tick(dt);
+            slow_motion.timer.finished()
+        }
+        None => false,
+    };
+    if over {
+        game_world.slow_motion = None;
+    }
+    for event in game_world.events.iter() {
+        if let Event::SlowTime { factor, seconds } = event {
+            game_world.slow_motion = Some(SlowMotion::new(*factor, *seconds));
+        }
+    }
+}
+
+/// Ends the run on the tick the mode's time limit runs out.
+pub fn time_limit_system(game_world: &mut GameWorld, dt: f64) {
+    let limit = match game_world.rules.time_limit {
+        Some(limit) => limit,
+        None => return,
+    };
+    if game_world.elapsed >= limit && game_world.elapsed - dt < limit {
+        game_world.events.push(Event::TimeUp);
+    }
+}
+
+/// Sets the pace of moves from how far the run has got, following the
+/// rules' speed curve, and slowed down by any slow motion. `scores` is
+/// indexed by player and should already include this tick's, so run it after
+/// `update_score_system`. The move timer keeps its phase, a move halfway done
+/// stays halfway done.
+pub fn speed_system(game_world: &mut GameWorld, scores: &[i32]) {
+    let rules = game_world.rules;
+    let progress = match rules.speed_basis {
+        SpeedBasis::Length => {
+            let longest = alive_players(&game_world.world)
+                .into_iter()
+                .map(|player| snake_length(game_world, player))
+                .max()
+                .unwrap_or(0);
+            // Snakes start out two cells long.
+            longest.saturating_sub(2) as u32
+        }
+        SpeedBasis::Score => scores.iter().copied().max().unwrap_or(0).max(0) as u32,
+    };
+    let mut duration = rules.move_time_at(progress);
+    if let Some(slow_motion) = &game_world.slow_motion {
+        duration *= slow_motion.factor;
+    }
+    if duration != game_world.move_timer.duration() {
+        game_world.move_timer.set_duration(duration);
+    }
+}
+
+/// Copies every `GridPos` into the `Position` the renderer draws from.
+pub fn sync_position_system(game_world: &mut GameWorld) {
+    let GameWorld { world, .. } = game_world;
+    for (_, (grid_pos, pos)) in
+        &mut world.query::<(&components::GridPos, &mut components::Position)>()
+    {
+        pos.0 = grid_pos.to_vec2();
+    }
+}
+
+/// Remembers the cell every moving entity starts the move tick on.
+pub fn store_previous_position_system(game_world: &mut GameWorld) {
+    let GameWorld { world, .. } = game_world;
+    for (_, (pos, previous)) in
+        &mut world.query::<(&components::GridPos, &mut components::PreviousGridPos)>()
+    {
+        previous.0 = *pos;
+    }
+}
+
+/// Fences the arena in with wall entities when its walls are solid, and puts
+/// up the level's walls inside it.
+pub fn create_arena_system(game_world: &mut GameWorld) {
+    let arena = game_world.play_arena();
+    let GameWorld {
+        world, grid, level, ..
+    } = game_world;
+    let mut cells = match arena.walls {
+        WallMode::Solid => arena.wall_cells(),
+        WallMode::Wrap => Vec::new(),
+    };
+    if let Some(level) = level {
+        cells.extend(level.walls.iter().copied());
+    }
+    for pos in cells {
+        let wall = world.spawn((
+            components::Wall,
+            pos,
+            components::Position(pos.to_vec2()),
+            components::Collision::wall(),
+            components::Material("Wall".into()),
+            components::Mesh("Wall".into()),
+        ));
+        grid.insert(wall, pos);
+    }
+}
+
+/// Spawns one snake per player with a single tail segment behind it, where
+/// the level says or spread evenly across the arena heading up.
+pub fn create_snake_system(game_world: &mut GameWorld) {
+    let players = game_world.players;
+    let arena = game_world.play_arena();
+    for player in 0..players {
+        let spawn = match &game_world.level {
+            Some(level) => level.spawn(player, players),
+            None => level::default_spawn(&arena, player, players),
+        };
+        let tail_pos = spawn.tail(&arena);
+        let GameWorld { world, grid, .. } = game_world;
+        let player = components::Player(player);
+        let ahead = spawn_snake_head(world, grid, player, spawn.pos, spawn.direction);
+        let tail = components::Tail { segment: 1, ahead };
+        spawn_snake_tail(world, grid, player, tail_pos, tail);
+    }
+}
+
+pub fn spawn_snake_head(
+    world: &mut hecs::World,
+    grid: &mut SpatialGrid,
+    player: components::Player,
+    pos: GridPos,
+    velocity: components::Direction,
+) -> hecs::Entity {
+    let head = world.spawn((
+        components::Snake,
+        player,
+        pos,
+        components::PreviousGridPos(pos),
+        components::Position(pos.to_vec2()),
+        components::Velocity(velocity),
+        components::HeadDirection(velocity),
+        components::TurnQueue::default(),
+        components::Material(player.head_material().into()),
+        components::Mesh("Snake".into()),
+    ));
+    grid.insert(head, pos);
+    head
+}
+
+pub fn spawn_snake_tail(
+    world: &mut hecs::World,
+    grid: &mut SpatialGrid,
+    player: components::Player,
+    pos: GridPos,
+    tail: components::Tail,
+) -> hecs::Entity {
+    let entity = world.spawn((
+        tail,
+        player,
+        components::Material(player.tail_material().into()),
+        components::Mesh("Tail".into()),
+        components::Collision::snake(),
+        pos,
+        components::PreviousGridPos(pos),
+        components::Position(pos.to_vec2()),
+    ));
+    grid.insert(entity, pos);
+    entity
+}
+
+/// Queues the turns `input` asks for, each snake listening to its own
+/// player's moves.
+pub fn update_input(game_world: &mut GameWorld, input: &components::Input) {
+    let mut input = *input;
+    for player in 0..game_world.players {
+        control_system(game_world, components::Player(player), &mut input);
+    }
+}
+
+/// Asks `controller` which way `player`'s snake should go and queues the
+/// turn it picks.
+pub fn control_system(
+    game_world: &mut GameWorld,
+    player: components::Player,
+    controller: &mut dyn SnakeController,
+) {
+    let direction = match controller.direction(game_world, player) {
+        Some(direction) => direction,
+        None => return,
+    };
+    let GameWorld { world, .. } = game_world;
+    for (_, (vel, dir, turns, owner)) in &mut world.query::<(
+        &components::Velocity,
+        &mut components::HeadDirection,
+        &mut components::TurnQueue,
+        &components::Player,
+    )>() {
+        if *owner == player && turns.push(direction, vel.0) {
+            dir.0 = direction;
+        }
+    }
+}
+
+/// Spawns food on a random free cell the level allows food on. Cells under
+/// the snake or other food are never picked. The player has won once snakes
+/// and walls cover every cell. Food left on the last free cells doesn't
+/// count, and like a full food zone it only means nothing spawns until a
+/// cell clears. Its kind is drawn by the weights in `food::FOOD_TABLE`, or
+/// always plain if the rules don't vary it.
+pub fn add_food_system(game_world: &mut GameWorld) {
+    let GameWorld {
+        world,
+        rng,
+        events,
+        arena,
+        grid,
+        rules,
+        level,
+        ..
+    } = game_world;
+    let food_count = world.query::<&components::Food>().iter().count();
+    if food_count >= rules.max_food {
+        return;
+    }
+
+    let open = |cell: &GridPos| {
+        grid.at(*cell)
+            .iter()
+            .all(|entity| world.get::<components::Food>(*entity).is_ok())
+    };
+    if !arena.cells().any(|cell| open(&cell)) {
+        events.push(Event::GameWon);
+        return;
+    }
+
+    let free: Vec<GridPos> = arena
+        .cells()
+        .filter(|cell| !grid.is_occupied(*cell))
+        .filter(|cell| level.as_ref().is_none_or(|level| level.allows_food(*cell)))
+        .collect();
+    if free.is_empty() {
+        return;
+    }
+
+    let pos = free[rng.gen_range(0, free.len() as i32) as usize];
+    let kind = if rules.varied_food {
+        food::pick(rng)
+    } else {
+        FoodKind::Plain
+    };
+    spawn_food(world, grid, pos, kind, rules.food_lifetime(kind));
+}
+
+/// Spawns a piece of food that disappears after `lifetime` seconds uneaten,
+/// or stays for good without one.
+pub fn spawn_food(
+    world: &mut hecs::World,
+    grid: &mut SpatialGrid,
+    pos: GridPos,
+    kind: FoodKind,
+    lifetime: Option<f64>,
+) -> hecs::Entity {
+    let spec = kind.spec();
+    let food = world.spawn((
+        pos,
+        components::Position(pos.to_vec2()),
+        components::Collision::food(),
+        components::Food(kind),
+        components::Material(spec.material.into()),
+        components::Mesh("Food".into()),
+    ));
+    if let Some(lifetime) = lifetime {
+        world
+            .insert_one(food, components::Lifetime::new(lifetime))
+            .expect("Food was just spawned");
+    }
+    grid.insert(food, pos);
+    food
+}
+
+/// Runs down the lifetime of all food and despawns what has outlasted it
+/// uneaten, with a `FoodExpired` for each. Runs before the move, so food
+/// can't be eaten on the tick it goes.
+pub fn expire_food_system(game_world: &mut GameWorld, dt: f64) {
+    let GameWorld {
+        world,
+        events,
+        grid,
+        ..
+    } = game_world;
+    let expired: Vec<(hecs::Entity, GridPos, FoodKind)> = world
+        .query::<(&mut components::Lifetime, &GridPos, &components::Food)>()
+        .iter()
+        .filter_map(|(entity, (lifetime, pos, food))| {
+            lifetime.0.tick(dt);
+            if lifetime.0.finished() {
+                Some((entity, *pos, food.0))
+            } else {
+                None
+            }
+        })
+        .collect();
+    for (entity, pos, kind) in expired {
+        grid.remove(entity, pos);
+        world
+            .despawn(entity)
+            .expect("Expired food was just queried and should still exist");
+        events.push(Event::FoodExpired { entity, pos, kind });
+    }
+}
+
+/// Makes the next queued turn, one per move tick.
+pub fn update_velocity_direction(game_world: &mut GameWorld) {
+    let GameWorld { world, .. } = game_world;
+    for (_, (velocity, direction, turns)) in &mut world.query::<(
+        &mut components::Velocity,
+        &mut components::HeadDirection,
+        &mut components::TurnQueue,
+    )>() {
+        if let Some(turn) = turns.pop() {
+            velocity.0 = turn;
+        }
+        direction.0 = turns.last().unwrap_or(velocity.0);
+    }
+}
+
+pub fn movement_system(game_world: &mut GameWorld) {
+    let arena = game_world.play_arena();
+    let GameWorld { world, grid, .. } = game_world;
+    for (entity, (pos, velocity)) in
+        &mut world.query::<(&mut components::GridPos, &components::Velocity)>()
+    {
+        let next = next_position(&arena, *pos, velocity.0);
+        grid.relocate(entity, *pos, next);
+        *pos = next;
+    }
+}
+
+/// Where something at `pos` ends up after one move, wrapping around the
+/// arena edges if it has no walls.
+pub(crate) fn next_position(
+    arena: &Arena,
+    pos: GridPos,
+    direction: components::Direction,
+) -> GridPos {
+    let next = pos.offset(direction);
+    match arena.walls {
+        WallMode::Wrap => arena.wrap(next),
+        WallMode::Solid => next,
+    }
+}
+
+/// Moves every segment onto the cell of the one ahead of it. The tails of
+/// dead snakes stay put.
+pub fn tail_movement_system(game_world: &mut GameWorld) {
+    let GameWorld { world, grid, .. } = game_world;
+    let ahead: HashMap<hecs::Entity, GridPos> = world
+        .query::<&components::Tail>()
+        .iter()
+        .map(|(_, tail)| {
+            let pos = {
+                *world
+                    .get::<components::GridPos>(tail.ahead)
+                    .expect("All Ahead should has GridPos")
+            };
+            (tail.ahead, pos)
+        })
+        .collect();
+    for (entity, (tail, position, dead)) in &mut world.query::<(
+        &components::Tail,
+        &mut components::GridPos,
+        Option<&components::Dead>,
+    )>() {
+        if dead.is_some() {
+            continue;
+        }
+        let new_pos = ahead[&tail.ahead];
+        grid.relocate(entity, *position, new_pos);
+        *position = new_pos;
+    }
+}
+
+/// Adds what the food was worth, times the mode's `food_points`, to the
+/// eating player's entry in `scores`, which is indexed by player.
+pub fn update_score_system(game_world: &mut GameWorld, scores: &mut [i32]) {
+    let GameWorld { events, rules, .. } = game_world;
+    for event in events {
+        if let Event::SnakeEatFood { player, kind, .. } = event {
+            if let Some(score) = scores.get_mut(player.0 as usize) {
+                *score += kind.spec().points * rules.food_points;
+            }
+        }
+    }
+}
+
+pub fn despawn_food_system(game_world: &mut GameWorld) {
+    let GameWorld {
+        world,
+        events,
+        grid,
+        ..
+    } = game_world;
+    for event in events {
+        if let Event::SnakeEatFood { entity, pos, .. } = event {
+            grid.remove(*entity, *pos);
+            world
+                .despawn(*entity)
+                .expect("Food Eating System should not be destroying a non-existant Entity");
+        }
+    }
+}
+
+/// Queues a new segment on the end of every snake that grows this tick.
+pub fn trigger_tail_spawn(game_world: &mut GameWorld) {
+    let GameWorld { world, events, .. } = game_world;
+    let mut events_to_push: Vec<Event> = Vec::new();
+    for event in events.iter() {
+        if let Event::Grow { player } = event {
+            if let Some((ahead, (tail, pos, _))) = &world
+                .query::<(&components::Tail, &components::GridPos, &components::Player)>()
+                .iter()
+                .filter(|(_, (_, _, owner))| *owner == player)
+                .max_by_key(|(_, (tail, _, _))| tail.segment)
+            {
+                events_to_push.push(Event::SpawnSnakeTail {
+                    ahead: *ahead,
+                    pos: **pos,
+                    segment: tail.segment + 1,
+                    player: *player,
+                })
+            }
+        }
+    }
+    events_to_push.iter().for_each(|evt| events.push(*evt));
+}
+
+pub fn spawn_tail_system(game_world: &mut GameWorld) {
+    let GameWorld {
+        world,
+        events,
+        grid,
+        ..
+    } = game_world;
+    for event in events.iter() {
+        if let Event::SpawnSnakeTail {
+            ahead,
+            pos,
+            segment,
+            player,
+        } = event
+        {
+            let tail = components::Tail {
+                segment: *segment,
+                ahead: *ahead,
+            };
+            spawn_snake_tail(world, grid, *player, *pos, tail);
+        }
+    }
+}
+
+/// Finds what every live head is about to run into. Heads have no
+/// `Collision` of their own while they move, so two heads meeting on the same
+/// cell, or passing through each other, are checked for here.
+pub fn head_collision_system(game_world: &mut GameWorld) {
+    let arena = game_world.play_arena();
+    let GameWorld {
+        world,
+        events,
+        grid,
+        ..
+    } = game_world;
+    let heads: Vec<(hecs::Entity, GridPos, GridPos)> = world
+        .query::<(
+            &components::Snake,
+            &components::GridPos,
+            &components::Velocity,
+        )>()
+        .iter()
+        .map(|(ent, (_, pos, vel))| (ent, *pos, next_position(&arena, *pos, vel.0)))
+        .collect();
+    for (source, pos, next) in heads.iter() {
+        grid.at(*next)
+            .iter()
+            .filter_map(|ent| {
+                let col = world.get::<components::Collision>(*ent).ok()?;
+                Some(Event::Collision {
+                    target: *ent,
+                    source: *source,
+                    pos: *next,
+                    kind: col.kind,
+                })
+            })
+            .for_each(|event| events.push(event));
+        heads
+            .iter()
+            .filter(|(other, other_pos, other_next)| {
+                other != source && (other_next == next || (other_next == pos && other_pos == next))
+            })
+            .for_each(|(other, _, _)| {
+                events.push(Event::Collision {
+                    target: *other,
+                    source: *source,
+                    pos: *next,
+                    kind: components::CollsionKind::Snake,
+                })
+            });
+    }
+}
+
+/// Turns collisions into deaths and meals. A snake that crashes this tick
+/// doesn't get to eat, even if there was food where it crashed. Where
+/// crashing doesn't kill, running into its own tail trims it instead and
+/// anything else is passed through.
+pub fn handle_collision_system(game_world: &mut GameWorld) {
+    let (collsions, rest): (Vec<Event>, Vec<Event>) = game_world
+        .events
+        .iter()
+        .cloned()
+        .partition(|event| matches!(event, Event::Collision { .. }));
+    game_world.events = rest;
+    let mut dead: Vec<hecs::Entity> = Vec::new();
+    for collision in collsions.iter() {
+        let (source, target, kind) = match collision {
+            Event::Collision {
+                source,
+                target,
+                kind,
+                ..
+            } => (*source, *target, *kind),
+            _ => continue,
+        };
+        if dead.contains(&source) {
+            continue;
+        }
+        let player = *game_world
+            .world
+            .get::<components::Player>(source)
+            .expect("Snake heads have a Player");
+        let cause = match kind {
+            components::CollsionKind::Wall => DeathCause::Wall,
+            components::CollsionKind::Snake => {
+                match game_world.world.get::<components::Player>(target) {
+                    Ok(owner) if *owner == player => DeathCause::Own,
+                    _ => DeathCause::Other,
+                }
+            }
+            components::CollsionKind::Food => continue,
+        };
+        if !game_world.rules.deadly {
+            if cause == DeathCause::Own {
+                if let Ok(tail) = game_world.world.get::<components::Tail>(target) {
+                    let segment = tail.segment;
+                    game_world.events.push(Event::TrimTail { player, segment });
+                }
+            }
+            continue;
+        }
+        dead.push(source);
+        game_world.events.push(Event::SnakeDied {
+            snake: source,
+            player,
+            cause,
+        });
+    }
+    let mut eaten: Vec<hecs::Entity> = Vec::new();
+    for collision in collsions.iter() {
+        if let Event::Collision {
+            kind: components::CollsionKind::Food,
+            target,
+            source,
+            ..
+        } = collision
+        {
+            if dead.contains(source) || eaten.contains(target) {
+                continue;
+            }
+            eaten.push(*target);
+            let entity = *target;
+            let pos = *game_world
+                .world
+                .get::<components::GridPos>(entity)
+                .expect("Food should have components::GridPos");
+            let player = *game_world
+                .world
+                .get::<components::Player>(*source)
+                .expect("Snake heads have a Player");
+            let kind = game_world
+                .world
+                .get::<components::Food>(entity)
+                .expect("Food should have components::Food")
+                .0;
+            game_world.events.push(Event::SnakeEatFood {
+                entity,
+                pos,
+                snake: *source,
+                player,
+                kind,
+            });
+        }
+    }
+}
+
+/// Leaves every snake that died this tick where it is, as an obstacle, and
+/// ends the run once there's nobody left to play. That is as soon as the
+/// only snake dies, or with more than one, when at most one is still alive.
+pub fn death_system(game_world: &mut GameWorld) {
+    let GameWorld { world, events, .. } = game_world;
+    let died: Vec<(hecs::Entity, components::Player)> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::SnakeDied { snake, player, .. } => Some((*snake, *player)),
+            _ => None,
+        })
+        .collect();
+    if died.is_empty() {
+        return;
+    }
+    for (snake, player) in died.iter() {
+        kill_snake(world, *snake, *player);
+    }
+
+    let alive = alive_players(world).len();
+    let players = world.query::<&components::Snake>().iter().count();
+    if players <= 1 || alive <= 1 {
+        events.push(Event::GameOver);
+    }
+}
+
+/// Turns what each snake ate this tick into the events that carry out its
+/// food's effects, as listed in `food::FOOD_TABLE`.
+pub fn food_effect_system(game_world: &mut GameWorld) {
+    let meals: Vec<(hecs::Entity, components::Player, FoodKind)> = game_world
+        .events
+        .iter()
+        .filter_map(|event| match event {
+            Event::SnakeEatFood {
+                snake,
+                player,
+                kind,
+                ..
+            } => Some((*snake, *player, *kind)),
+            _ => None,
+        })
+        .collect();
+    for (snake, player, kind) in meals {
+        for effect in kind.spec().effects {
+            let event = match *effect {
+                FoodEffect::Grow => Event::Grow { player },
+                FoodEffect::Shrink(segments) => {
+                    let last = game_world
+                        .world
+                        .query::<(&components::Tail, &components::Player)>()
+                        .iter()
+                        .filter(|(_, (_, owner))| **owner == player)
+                        .map(|(_, (tail, _))| tail.segment)
+                        .max()
+                        .unwrap_or(0);
+                    // Segment 1 stays whatever happens.
+                    let segment = (last + 1).saturating_sub(segments).max(2);
+                    if segment > last {
+                        continue;
+                    }
+                    Event::TrimTail { player, segment }
+                }
+                FoodEffect::SlowTime { factor, seconds } => Event::SlowTime { factor, seconds },
+                FoodEffect::Poison if game_world.rules.deadly => Event::SnakeDied {
+                    snake,
+                    player,
+                    cause: DeathCause::Poison,
+                },
+                FoodEffect::Poison => continue,
+            };
+            game_world.events.push(event);
+        }
+    }
+}
+
+/// Despawns the part of every snake that was bitten or shrunk off this tick.
+pub fn trim_tail_system(game_world: &mut GameWorld) {
+    let GameWorld {
+        world,
+        events,
+        grid,
+        ..
+    } = game_world;
+    let trims: Vec<(components::Player, usize)> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::TrimTail { player, segment } => Some((*player, *segment)),
+            _ => None,
+        })
+        .collect();
+    for (player, segment) in trims {
+        let bitten: Vec<(hecs::Entity, GridPos)> = world
+            .query::<(&components::Tail, &components::Player, &GridPos)>()
+            .iter()
+            .filter(|(_, (tail, owner, _))| **owner == player && tail.segment >= segment)
+            .map(|(entity, (_, _, pos))| (entity, *pos))
+            .collect();
+        for (entity, pos) in bitten {
+            grid.remove(entity, pos);
+            world
+                .despawn(entity)
+                .expect("Segment was just queried and should still exist");
+        }
+    }
+}
+
+/// Stops `player`'s snake, whose head is `snake`, and turns all of it into
+/// an obstacle.
+pub fn kill_snake(world: &mut hecs::World, snake: hecs::Entity, player: components::Player) {
+    let _ = world.remove::<(components::Velocity, components::TurnQueue)>(snake);
+    let segments: Vec<hecs::Entity> = world
+        .query::<&components::Player>()
+        .iter()
+        .filter(|(_, owner)| **owner == player)
+        .map(|(entity, _)| entity)
+        .collect();
+    for segment in segments {
+        world
+            .insert_one(segment, components::Dead)
+            .expect("Segment was just queried");
+    }
+    world
+        .insert_one(snake, components::Collision::snake())
+        .expect("Snake head should still exist");
+}
+
+/// Every player whose snake is still moving.
+pub fn alive_players(world: &hecs::World) -> Vec<components::Player> {
+    world
+        .query::<(
+            &components::Snake,
+            &components::Player,
+            Option<&components::Dead>,
+        )>()
+        .iter()
+        .filter(|(_, (_, _, dead))| dead.is_none())
+        .map(|(_, (_, player, _))| *player)
+        .collect()
+}
+
+/// How a run came to an end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    /// The only snake crashed.
+    Lost,
+    /// The board filled up.
+    Won,
+    /// Every other snake crashed first.
+    Winner(components::Player),
+    /// The last snakes standing crashed on the same tick.
+    Draw,
+    /// The mode's time limit ran out.
+    TimeUp,
+}
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Outcome::Lost => "lost",
+            Outcome::Won => "won",
+            Outcome::Winner(_) => "winner",
+            Outcome::Draw => "draw",
+            Outcome::TimeUp => "time_up",
+        }
+    }
+}
+
+/// How one player did in a finished run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerStats {
+    pub player: components::Player,
+    pub score: i32,
+    /// Cells the snake covered, head included.
+    pub length: usize,
+    pub alive: bool,
+}
+
+/// What a finished run amounted to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunStats {
+    pub outcome: Outcome,
+    /// Seconds the run lasted.
+    pub time: f64,
+    /// One entry per snake, in player order.
+    pub players: Vec<PlayerStats>,
+}
+
+impl RunStats {
+    /// `scores` is indexed by player, like it is for `update_score_system`.
+    pub fn capture(game_world: &GameWorld, outcome: Outcome, scores: &[i32]) -> Self {
+        let alive = alive_players(&game_world.world);
+        let mut players: Vec<components::Player> = game_world
+            .world
+            .query::<(&components::Snake, &components::Player)>()
+            .iter()
+            .map(|(_, (_, player))| *player)
+            .collect();
+        players.sort_by_key(|player| player.0);
+        let players = players
+            .into_iter()
+            .map(|player| PlayerStats {
+                player,
+                score: scores.get(player.0 as usize).copied().unwrap_or(0),
+                length: snake_length(game_world, player),
+                alive: alive.contains(&player),
+            })
+            .collect();
+        Self {
+            outcome,
+            time: game_world.elapsed,
+            players,
+        }
+    }
+}
+
+pub fn snake_length(game_world: &GameWorld, player: components::Player) -> usize {
+    let GameWorld { world, .. } = game_world;
+    world
+        .query::<(&components::Player, &components::GridPos)>()
+        .iter()
+        .filter(|(_, (owner, _))| **owner == player)
+        .count()
+}
+
+/// Reports whether the run ended this tick. The board is left as it was so
+/// the caller can look at it before calling `reset_system`.
+pub fn game_over_system(game_world: &GameWorld) -> Option<Outcome> {
+    game_world.events.iter().find_map(|event| match event {
+        Event::GameOver => {
+            let snakes = game_world
+                .world
+                .query::<&components::Snake>()
+                .iter()
+                .count();
+            if snakes <= 1 {
+                return Some(Outcome::Lost);
+            }
+            match alive_players(&game_world.world).as_slice() {
+                [winner] => Some(Outcome::Winner(*winner)),
+                _ => Some(Outcome::Draw),
+            }
+        }
+        Event::GameWon => Some(Outcome::Won),
+        Event::TimeUp => Some(Outcome::TimeUp),
+        _ => None,
+    })
+}
+
+/// Puts the board back the way a new run starts.
+pub fn reset_system(game_world: &mut GameWorld) {
+    clear_simulation_system(game_world);
+    game_world.apply_rules();
+    game_world.slow_motion = None;
+    create_arena_system(game_world);
+    create_snake_system(game_world);
+    game_world.elapsed = 0.;
+}
+
+/// Despawns everything that lives on the grid. Entities the front end added,
+/// like the score text, are left alone.
+pub fn clear_simulation_system(game_world: &mut GameWorld) {
+    let GameWorld { world, grid, .. } = game_world;
+    let entities: Vec<hecs::Entity> = world
+        .query::<&components::GridPos>()
+        .iter()
+        .map(|(entity, _)| entity)
+        .collect();
+    for entity in entities {
+        world
+            .despawn(entity)
+            .expect("Entity was just queried and should still exist");
+    }
+    grid.clear();
+    game_world.run += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::STEP;
+    use crate::components::{Direction, Player};
+
+    /// A new run of `mode` with its snake in place.
+    fn mode_world(mode: GameMode) -> GameWorld {
+        let mut game_world = GameWorld::new(0);
+        game_world.set_mode(mode);
+        create_arena_system(&mut game_world);
+        create_snake_system(&mut game_world);
+        game_world
+    }
+
+    /// Steps the world the way the front end does, and reports whether the
+    /// run ended.
+    fn tick(game_world: &mut GameWorld, scores: &mut [i32], dt: f64) -> Option<Outcome> {
+        game_world.events.clear();
+        step_system(game_world, dt);
+        despawn_food_system(game_world);
+        update_score_system(game_world, scores);
+        speed_system(game_world, scores);
+        game_over_system(game_world)
+    }
+
+    fn head(game_world: &GameWorld) -> (GridPos, Direction) {
+        let mut query = game_world
+            .world
+            .query::<(&components::Snake, &GridPos, &components::Velocity)>();
+        let (_, (_, pos, vel)) = query.iter().next().unwrap();
+        (*pos, vel.0)
+    }
+
+    fn food_count(game_world: &GameWorld) -> usize {
+        game_world.world.query::<&components::Food>().iter().count()
+    }
+
+    fn won(game_world: &GameWorld) -> bool {
+        game_world
+            .events
+            .iter()
+            .any(|event| matches!(event, Event::GameWon))
+    }
+
+    /// A small level whose only food cell is its first one, with a snake on
+    /// it.
+    fn zoned_world() -> (GameWorld, GridPos) {
+        let mut level = Level::open("zoned", Arena::new(8, 8, WallMode::Solid));
+        let zone = level.arena.cells().next().unwrap();
+        level.food.insert(zone);
+        let mut game_world = GameWorld::new(0);
+        game_world.set_level(level);
+        create_arena_system(&mut game_world);
+        create_snake_system(&mut game_world);
+        (game_world, zone)
+    }
+
+    #[test]
+    fn a_full_food_zone_skips_the_spawn() {
+        let (mut game_world, zone) = zoned_world();
+        let GameWorld { world, grid, .. } = &mut game_world;
+        spawn_food(world, grid, zone, FoodKind::Plain, None);
+
+        add_food_system(&mut game_world);
+        assert!(!won(&game_world));
+        assert_eq!(food_count(&game_world), 1);
+    }
+
+    #[test]
+    fn food_on_the_last_free_cells_doesnt_win() {
+        let (mut game_world, _) = zoned_world();
+        game_world.rules.max_food = usize::MAX;
+        let free: Vec<GridPos> = game_world
+            .arena
+            .cells()
+            .filter(|cell| !game_world.grid.is_occupied(*cell))
+            .collect();
+        let GameWorld { world, grid, .. } = &mut game_world;
+        for cell in free.iter() {
+            spawn_food(world, grid, *cell, FoodKind::Plain, None);
+        }
+
+        add_food_system(&mut game_world);
+        assert!(!won(&game_world));
+        assert_eq!(food_count(&game_world), free.len());
+    }
+
+    #[test]
+    fn a_snake_filling_the_arena_wins() {
+        // Walls everywhere but where the snake starts.
+        let mut level = Level::open("full", Arena::new(4, 4, WallMode::Solid));
+        let spawn = level.spawn(0, 1);
+        let snake = [spawn.pos, spawn.tail(&level.arena)];
+        level.walls = level
+            .arena
+            .cells()
+            .filter(|cell| !snake.contains(cell))
+            .collect();
+        let mut game_world = GameWorld::new(0);
+        game_world.set_level(level);
+        create_arena_system(&mut game_world);
+        create_snake_system(&mut game_world);
+
+        add_food_system(&mut game_world);
+        assert!(won(&game_world));
+        assert_eq!(food_count(&game_world), 0);
+    }
+
+    /// Steps of `STEP` that add up to `seconds`.
+    fn steps(seconds: f64) -> usize {
+        (seconds / STEP).round() as usize
+    }
+
+    #[test]
+    fn classic_moves_every_quarter_second_and_feeds_every_second_and_a_half() {
+        let mut game_world = mode_world(GameMode::Classic);
+        assert_eq!(game_world.rules.move_time, 0.25);
+        assert_eq!(game_world.rules.food_time, 1.5);
+        assert_eq!(game_world.rules.time_limit, None);
+        let mut scores = [0];
+
+        // The steps on which the snake moved and food turned up, over the
+        // first three and a half seconds. Its head hits the wall not long
+        // after.
+        let mut moves = Vec::new();
+        let mut feeds = Vec::new();
+        let mut last = head(&game_world).0;
+        for step in 1..=steps(3.5) {
+            tick(&mut game_world, &mut scores, STEP);
+            if head(&game_world).0 != last {
+                last = head(&game_world).0;
+                moves.push(step);
+            }
+            if food_count(&game_world) > feeds.len() {
+                feeds.push(step);
+            }
+        }
+
+        // Sixty steps add up to a hair under a second, so the first of
+        // anything can come a step late. The timers keep what they ran
+        // over, so the ones after it don't drift any further.
+        let move_steps = steps(0.25);
+        assert_eq!(move_steps, 15);
+        assert!((move_steps..=move_steps + 1).contains(&moves[0]));
+        assert!(moves.len() >= 13);
+        assert!(moves.windows(2).all(|pair| pair[1] - pair[0] == move_steps));
+
+        let food_steps = steps(1.5);
+        assert_eq!(food_steps, 90);
+        assert!((food_steps..=food_steps + 1).contains(&feeds[0]));
+        assert_eq!(feeds.len(), 2);
+        assert_eq!(feeds[1] - feeds[0], food_steps);
+        assert_eq!(game_world.move_timer.duration(), 0.25);
+    }
+
+    #[test]
+    fn time_attack_ends_after_two_minutes() {
+        // Without a snake nothing else can end the run.
+        let mut game_world = GameWorld::new(0);
+        game_world.set_mode(GameMode::TimeAttack);
+        create_arena_system(&mut game_world);
+        let mut scores = [0];
+
+        let limit = steps(120.);
+        assert_eq!(limit, 7200);
+        let mut step = 1;
+        while tick(&mut game_world, &mut scores, STEP).is_none() {
+            step += 1;
+            assert!(step <= limit + 1, "still going after two minutes");
+        }
+        assert!(step >= limit, "over after {} steps", step);
+        assert_eq!(game_over_system(&game_world), Some(Outcome::TimeUp));
+    }
+
+    #[test]
+    fn survival_moves_faster_as_the_snake_grows() {
+        let mut game_world = mode_world(GameMode::Survival);
+        let start = game_world.rules.move_time;
+        let mut scores = [0];
+        let (pos, direction) = head(&game_world);
+        let ahead = next_position(&game_world.play_arena(), pos, direction);
+        let GameWorld { world, grid, .. } = &mut game_world;
+        spawn_food(world, grid, ahead, FoodKind::Plain, None);
+
+        for _ in 0..=steps(start) {
+            tick(&mut game_world, &mut scores, STEP);
+        }
+        assert_eq!(snake_length(&game_world, Player(0)), 3);
+        assert!(game_world.move_timer.duration() < start);
+        assert_eq!(
+            game_world.move_timer.duration(),
+            game_world.rules.move_time_at(1)
+        );
+    }
+
+    #[test]
+    fn zen_trims_the_tail_instead_of_dying() {
+        let mut game_world = GameWorld::new(0);
+        game_world.set_mode(GameMode::Zen);
+        // A straight snake whose head faces its own tail, so its next move
+        // runs into its second segment.
+        let player = Player(0);
+        let head_pos = GridPos(0, 0);
+        let GameWorld { world, grid, .. } = &mut game_world;
+        let mut ahead = spawn_snake_head(world, grid, player, head_pos, Direction::Right);
+        let mut pos = head_pos;
+        for segment in 1..=4 {
+            pos = pos.offset(Direction::Right);
+            let tail = components::Tail { segment, ahead };
+            ahead = spawn_snake_tail(world, grid, player, pos, tail);
+        }
+        let move_time = game_world.rules.move_time;
+        let mut scores = [0];
+
+        let outcome = tick(&mut game_world, &mut scores, move_time);
+        assert_eq!(outcome, None);
+        assert!(!game_world
+            .events
+            .iter()
+            .any(|event| matches!(event, Event::SnakeDied { .. })));
+        assert!(game_world
+            .events
+            .iter()
+            .any(|event| matches!(event, Event::TrimTail { segment: 2, .. })));
+        assert_eq!(alive_players(&game_world.world), vec![player]);
+        assert_eq!(snake_length(&game_world, player), 2);
+    }
+}